@@ -19,6 +19,9 @@ pub enum UnOp {
     Deref,
 
     Negate,
+    Minus,
+
+    Len,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -69,6 +72,8 @@ pub enum ExprKind {
     Unary(UnOp, Box<Expr>),
     Binary(BinOp, Box<Expr>, Box<Expr>),
     FunCall(Symbol, Vec<Expr>),
+    Tuple(Vec<Expr>),
+    Vector(Vec<Expr>),
 }
 
 #[derive(Debug, Clone)]
@@ -77,6 +82,16 @@ pub struct Expr {
     pub loc: Loc,
 }
 
+// `-e`, starting at `lo`. Minus applied to a parenthesized integer literal
+// is folded into a literal, unless negating it overflows.
+pub fn minus(lo: usize, e: Expr) -> ExprKind {
+    match e.expr {
+        ExprKind::Lit(Lit { lit: LiteralKind::LInt(n), loc: (_, hi) }) if n != i64::MIN =>
+            ExprKind::Lit(Lit { lit: LiteralKind::LInt(-n), loc: (lo, hi) }),
+        _ => ExprKind::Unary(UnOp::Minus, Box::new(e)),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum LValKind {
     VarRef(Symbol),
@@ -135,12 +150,15 @@ pub enum StatementKind {
     Assign(Expr, Expr),
     Block(Vec<Statement>),
     Case(Expr, Vec<CaseBranch>),
+    While(Expr, Box<Statement>),
+    Return(Expr),
+    Push(Expr, Expr),
 }
 
-pub fn if_statement(e: Expr, s: Statement) -> StatementKind {
+fn bool_branch(b: bool, s: Statement) -> CaseBranch {
     let l = s.loc;
     let tt = PatternKind::PLiteral(Lit {
-        lit: LiteralKind::LBool(true),
+        lit: LiteralKind::LBool(b),
         loc: l,
     });
     let pat = Pattern {
@@ -148,11 +166,18 @@ pub fn if_statement(e: Expr, s: Statement) -> StatementKind {
             loc: s.loc
     };
     let branch = CaseBranchKind::CaseArm(pat, Box::new(s));
-    let then_branch = CaseBranch {
+    CaseBranch {
         branch,
         loc: l,
-    };
-    StatementKind::Case(e, vec![then_branch])
+    }
+}
+
+pub fn if_statement(e: Expr, s: Statement) -> StatementKind {
+    StatementKind::Case(e, vec![bool_branch(true, s)])
+}
+
+pub fn if_else_statement(e: Expr, s1: Statement, s2: Statement) -> StatementKind {
+    StatementKind::Case(e, vec![bool_branch(true, s1), bool_branch(false, s2)])
 }
 
 impl StatementKind {
@@ -175,9 +200,17 @@ pub struct FnDecl {
     pub loc: Loc,
 }
 
+// A global variable, allocated once before `main` runs
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub bind: TypeBind,
+    pub init: Option<Expr>,
+    pub loc: Loc,
+}
+
 #[derive(Debug, Clone)]
 pub struct Module {
-    pub globals: Vec<TypeBind>,
+    pub globals: Vec<Global>,
     pub functions: Vec<FnDecl>,
     pub loc: Loc,
 }
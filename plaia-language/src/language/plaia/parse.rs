@@ -1,39 +1,227 @@
 use crate::language::plaia::ast::*;
+use crate::language::plaia::source::SourceFile;
+use lalrpop_util::ParseError as LalrpopError;
+use lalrpop_util::lexer::Token;
+use std::fmt;
 
-lalrpop_mod!(pub plaia, "/language/plaia/plaia.rs");
+lalrpop_mod!(#[allow(clippy::all)] pub plaia, "/language/plaia/plaia.rs");
 
-pub fn parse_expression(inp: &str) -> Expr {
-    plaia::ExprParser::new().parse(inp).unwrap()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub loc: Loc,
+    pub expected: Vec<String>,
+    pub message: String,
+    pub rendered: String,
 }
-pub fn parse_statement(inp: &str) -> Statement {
-    plaia::StatementParser::new().parse(inp).unwrap()
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rendered)
+    }
 }
-pub fn parse_function(inp: &str) -> FnDecl {
-    plaia::FnDeclParser::new().parse(inp).unwrap()
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    fn new(file: &SourceFile, err: LalrpopError<usize, Token<'_>, (Loc, &'static str)>) -> ParseError {
+        let (loc, expected, message) = match err {
+            LalrpopError::InvalidToken { location } =>
+                ((location, location + 1), Vec::new(), "invalid token".to_string()),
+            LalrpopError::UnrecognizedEOF { location, expected } =>
+                ((location, location), expected, "unexpected end of input".to_string()),
+            LalrpopError::UnrecognizedToken { token: (lo, t, hi), expected } =>
+                ((lo, hi), expected, format!("unexpected token `{}`", t.1)),
+            LalrpopError::ExtraToken { token: (lo, t, hi) } =>
+                ((lo, hi), Vec::new(), format!("extra token `{}`", t.1)),
+            LalrpopError::User { error: (loc, msg) } =>
+                (loc, Vec::new(), msg.to_string()),
+        };
+        let rendered = render(file, loc, &message, &expected);
+        ParseError { loc, expected, message, rendered }
+    }
+}
+
+// Render `message` followed by the source line containing `loc.0`, with a
+// caret underneath the offending span.
+fn render(file: &SourceFile, loc: Loc, message: &str, expected: &[String]) -> String {
+    let mut out = format!("error: {}", message);
+    if !expected.is_empty() {
+        out.push_str(&format!(", expected one of {}", expected.join(", ")));
+    }
+    out.push('\n');
+    out.push_str(&file.annotate(loc));
+    out
 }
-pub fn parse_module(inp: &str) -> Module {
-    plaia::ModuleParser::new().parse(inp).unwrap()
+
+fn parse_with<T, P>(inp: &str, parse: P) -> Result<T, ParseError>
+where
+    P: FnOnce(&str) -> Result<T, LalrpopError<usize, Token<'_>, (Loc, &'static str)>>,
+{
+    parse(inp).map_err(|e| ParseError::new(&SourceFile::new(String::new(), inp.to_string()), e))
+}
+
+pub fn parse_expression(inp: &str) -> Result<Expr, ParseError> {
+    parse_with(inp, |s| plaia::ExprParser::new().parse(s))
+}
+pub fn parse_statement(inp: &str) -> Result<Statement, ParseError> {
+    parse_with(inp, |s| plaia::StatementParser::new().parse(s))
+}
+pub fn parse_function(inp: &str) -> Result<FnDecl, ParseError> {
+    parse_with(inp, |s| plaia::FnDeclParser::new().parse(s))
+}
+pub fn parse_module(inp: &str) -> Result<Module, ParseError> {
+    parse_with(inp, |s| plaia::ModuleParser::new().parse(s))
+}
+// Like `parse_module`, but errors name the file they occurred in.
+pub fn parse_source(file: &SourceFile) -> Result<Module, ParseError> {
+    plaia::ModuleParser::new().parse(&file.src).map_err(|e| ParseError::new(file, e))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::language::plaia::parse::parse_expression;
-    use crate::language::plaia::ast::*;
+    use crate::language::plaia::parse::*;
 
     #[test]
     fn test_expr() {
-        let e = parse_expression("3");
+        let e = parse_expression("3").unwrap();
         if let ExprKind::Lit(l) = e.expr {
             assert_eq!(l.lit, LiteralKind::LInt(3));
         } else {
-            assert!(false);
+            panic!("expected a literal");
         }
     }
 
     #[test]
     fn test_expr1() {
-        let e = parse_expression("f(1, 2, 3) + 4*6");
+        let e = parse_expression("f(1, 2, 3) + 4*6").unwrap();
         println!("{:?}", e)
     }
 
+    #[test]
+    fn test_while() {
+        let s = parse_statement("while x < 10 do { x = x + 1; }").unwrap();
+        if let StatementKind::While(e, body) = s.stmt {
+            assert!(matches!(e.expr, ExprKind::Binary(BinOp::Lt, _, _)));
+            assert!(matches!(body.stmt, StatementKind::Block(_)));
+        } else {
+            panic!("expected a while loop");
+        }
+    }
+
+    #[test]
+    fn test_unary() {
+        let e = parse_expression("a - -3 * -(b - 1)").unwrap();
+        if let ExprKind::Binary(BinOp::Sub, _, r) = e.expr {
+            if let ExprKind::Binary(BinOp::Mul, l, r) = r.expr {
+                assert!(matches!(l.expr, ExprKind::Lit(Lit { lit: LiteralKind::LInt(-3), loc: (4, 6) })));
+                assert!(matches!(r.expr, ExprKind::Unary(UnOp::Minus, _)));
+                return;
+            }
+        }
+        panic!("expected a - ((-3) * -(b - 1))");
+    }
+
+    #[test]
+    fn test_tuples() {
+        let e = parse_expression("(1, (x, true), f(2))[1]").unwrap();
+        if let ExprKind::Binary(BinOp::Proj, t, _) = e.expr {
+            assert!(matches!(&t.expr, ExprKind::Tuple(es) if es.len() == 3));
+        } else {
+            panic!("expected a projection");
+        }
+        let s = parse_statement("let p : (i64, (bool, i64*)) = (1, (true, &x));").unwrap();
+        if let StatementKind::VarDecl(tb, _) = s.stmt {
+            assert_eq!(tb.bind_type,
+                       Type::Tuple(vec![Type::I64,
+                                        Type::Tuple(vec![Type::Bool, Type::Ptr(Box::new(Type::I64))])]));
+        } else {
+            panic!("expected a declaration");
+        }
+        assert!(matches!(parse_expression("(1)").unwrap().expr, ExprKind::Lit(_)));
+    }
+
+    #[test]
+    fn test_vectors() {
+        let e = parse_expression("len([1, x][0]) * v[2][3]").unwrap();
+        if let ExprKind::Binary(BinOp::Mul, l, r) = e.expr {
+            assert!(matches!(&l.expr, ExprKind::Unary(UnOp::Len, _)));
+            assert!(matches!(&r.expr, ExprKind::Binary(BinOp::Proj, _, _)));
+        } else {
+            panic!("expected a product");
+        }
+        let s = parse_statement("{ push(v, 1); v[0] = 2; }").unwrap();
+        if let StatementKind::Block(ss) = s.stmt {
+            assert!(matches!(&ss[0].stmt, StatementKind::Push(_, _)));
+            assert!(matches!(&ss[1].stmt,
+                             StatementKind::Assign(Expr { expr: ExprKind::Binary(BinOp::Proj, _, _), .. }, _)));
+        } else {
+            panic!("expected a block");
+        }
+    }
+
+    fn arms(s: &Statement) -> &Vec<CaseBranch> {
+        if let StatementKind::Case(_, bs) = &s.stmt { bs } else { panic!("expected a case") }
+    }
+
+    #[test]
+    fn test_if_else() {
+        // The `else` belongs to the inner `if`
+        let s = parse_statement("if a then if b then x = 1; else x = 2;").unwrap();
+        let outer = arms(&s);
+        assert_eq!(outer.len(), 1);
+        let CaseBranchKind::CaseArm(_, inner) = &outer[0].branch;
+        let inner = arms(inner);
+        assert_eq!(inner.len(), 2);
+        let CaseBranchKind::CaseArm(p, _) = &inner[1].branch;
+        assert!(matches!(&p.pattern,
+                         PatternKind::PLiteral(Lit { lit: LiteralKind::LBool(false), .. })));
+    }
+
+    #[test]
+    fn test_case() {
+        let s = parse_statement("case x of { 0 => y = 1;, -1 => {}, z => y = z;, _ => y = 2; }").unwrap();
+        let pats: Vec<_> = arms(&s).iter().map(|b| {
+            let CaseBranchKind::CaseArm(p, _) = &b.branch;
+            p.pattern.clone()
+        }).collect();
+        assert!(matches!(&pats[0], PatternKind::PLiteral(Lit { lit: LiteralKind::LInt(0), .. })));
+        assert!(matches!(&pats[1], PatternKind::PLiteral(Lit { lit: LiteralKind::LInt(-1), .. })));
+        assert!(matches!(&pats[2], PatternKind::PSymbol(x) if x.name == "z"));
+        assert!(matches!(&pats[3], PatternKind::PWild));
+    }
+
+    #[test]
+    fn test_globals() {
+        let m = parse_module("n : i64 = 2 * 3 p : i64* def main(args : vec<i64>) = {}").unwrap();
+        assert_eq!(m.globals.len(), 2);
+        assert_eq!(m.globals[0].bind.name.name, "n");
+        assert_eq!(m.globals[0].loc, (0, 15));
+        assert!(matches!(&m.globals[0].init, Some(Expr { expr: ExprKind::Binary(BinOp::Mul, _, _), .. })));
+        assert!(m.globals[1].init.is_none());
+        assert_eq!(m.functions.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let src = "def main(args : vec<i64>) = {\n  let x : i64 = );\n}";
+        let err = parse_module(src).unwrap_err();
+        assert_eq!(err.loc, (46, 47));
+        assert!(!err.expected.is_empty());
+        assert!(err.rendered.contains("2:17"));
+        let file = SourceFile::new("foo.pl".to_string(), src.to_string());
+        assert!(parse_source(&file).unwrap_err().rendered.contains("--> foo.pl:2:17"));
+        assert!(err.rendered.ends_with("  let x : i64 = );\n  |                 ^"));
+    }
+
+    #[test]
+    fn test_literal_overflow() {
+        let err = parse_expression("99999999999999999999").unwrap_err();
+        assert_eq!(err.loc, (0, 20));
+        assert!(parse_expression("9223372036854775808").is_err());
+        let e = parse_expression("-9223372036854775808").unwrap();
+        assert!(matches!(e.expr, ExprKind::Lit(Lit { lit: LiteralKind::LInt(i64::MIN), .. })));
+        let s = parse_statement("case x of { -9223372036854775808 => {}, _ => {} }").unwrap();
+        let CaseBranchKind::CaseArm(p, _) = &arms(&s)[0].branch;
+        assert!(matches!(p.pattern, PatternKind::PLiteral(Lit { lit: LiteralKind::LInt(i64::MIN), .. })));
+    }
 }
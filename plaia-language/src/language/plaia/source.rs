@@ -0,0 +1,210 @@
+use crate::language::plaia::ast::Loc;
+use std::fmt;
+
+// Every AST node carries a `Loc`, a pair of byte offsets into the text it was
+// parsed from. A `SourceFile` remembers where each line of that text starts so
+// that offsets can be turned into (1-based) lines and columns and back again.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub src: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: String, src: String) -> SourceFile {
+        let mut line_starts = vec![0];
+        line_starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+        SourceFile { name, src, line_starts }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // Offsets past the end of the file are clamped to the end of the file.
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.src.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(l) => l,
+            Err(l) => l - 1,
+        };
+        let start = self.line_starts[line];
+        let column = self.src.get(start..offset).map_or(offset - start, |s| s.chars().count());
+        Position { line: line + 1, column: column + 1 }
+    }
+
+    pub fn offset(&self, pos: Position) -> Option<usize> {
+        let start = *self.line_starts.get(pos.line.checked_sub(1)?)?;
+        let text  = self.line(pos.line)?;
+        let col   = pos.column.checked_sub(1)?;
+        if col == text.chars().count() {
+            Some(start + text.len())
+        } else {
+            text.char_indices().nth(col).map(|(i, _)| start + i)
+        }
+    }
+
+    // The text of (1-based) line `line`, without its trailing newline.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end   = self.line_starts.get(line).map_or(self.src.len(), |e| e - 1);
+        Some(self.src[start..end].trim_end_matches('\r'))
+    }
+
+    pub fn snippet(&self, loc: Loc) -> &str {
+        let hi = loc.1.min(self.src.len());
+        self.src.get(loc.0.min(hi)..hi).unwrap_or("")
+    }
+
+    // The first line of the snippet at `loc`, e.g. to name a statement.
+    pub fn first_line(&self, loc: Loc) -> &str {
+        self.snippet(loc).lines().next().unwrap_or("")
+    }
+
+    // The location of `loc` followed by the line it starts on, with a caret
+    // underneath the span:
+    //
+    //  --> foo.pl:2:9
+    //   |
+    // 2 |   x = x + ;
+    //   |         ^
+    pub fn annotate(&self, loc: Loc) -> String {
+        let start = self.position(loc.0);
+        let end   = self.position(loc.1);
+        let text  = self.line(start.line).unwrap_or("");
+        let width = if end.line == start.line && end.column > start.column {
+            end.column - start.column
+        } else {
+            1
+        };
+
+        let gutter = " ".repeat(start.line.to_string().len());
+        let mut out = format!("{}--> {}\n", gutter, self.location(loc));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", start.line, text));
+        out.push_str(&format!("{} | {}{}", gutter, " ".repeat(start.column - 1), "^".repeat(width)));
+        out
+    }
+
+    pub fn location(&self, loc: Loc) -> SourceLocation<'_> {
+        SourceLocation {
+            file: &self.name,
+            start: self.position(loc.0),
+            end: self.position(loc.1),
+        }
+    }
+}
+
+// A resolved `Loc`; displays as `foo.pl:12:5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    pub file: &'a str,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl fmt::Display for SourceLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "{}:{}", self.start.line, self.start.column)
+        } else {
+            write!(f, "{}:{}:{}", self.file, self.start.line, self.start.column)
+        }
+    }
+}
+
+// A collection of source files. Each file is assigned a disjoint range of
+// global offsets, so a `Loc` that has been shifted by `start_of` the file it
+// came from identifies both the file and the position within it.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<(usize, SourceFile)>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap { files: Vec::new() }
+    }
+
+    // Returns the global offset at which the new file starts.
+    pub fn add_file(&mut self, name: String, src: String) -> usize {
+        let start = self.files.last().map_or(0, |(s, f)| s + f.src.len() + 1);
+        self.files.push((start, SourceFile::new(name, src)));
+        start
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter().map(|(_, f)| f)
+    }
+
+    pub fn start_of(&self, name: &str) -> Option<usize> {
+        self.files.iter().find(|(_, f)| f.name == name).map(|(s, _)| *s)
+    }
+
+    pub fn lookup_file(&self, offset: usize) -> Option<(usize, &SourceFile)> {
+        let idx = match self.files.binary_search_by_key(&offset, |(s, _)| *s) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (start, file) = &self.files[idx];
+        if offset <= start + file.src.len() { Some((*start, file)) } else { None }
+    }
+
+    pub fn lookup(&self, offset: usize) -> Option<(&SourceFile, Position)> {
+        self.lookup_file(offset).map(|(start, f)| (f, f.position(offset - start)))
+    }
+
+    pub fn location(&self, loc: Loc) -> Option<SourceLocation<'_>> {
+        self.lookup_file(loc.0).map(|(start, f)| f.location((loc.0 - start, loc.1 - start)))
+    }
+
+    pub fn offset(&self, name: &str, pos: Position) -> Option<usize> {
+        self.files.iter()
+            .find(|(_, f)| f.name == name)
+            .and_then(|(s, f)| f.offset(pos).map(|o| s + o))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::source::*;
+
+    #[test]
+    fn test_position_roundtrip() {
+        let f = SourceFile::new("foo.pl".to_string(), "ab\ncdé\n\nx".to_string());
+        assert_eq!(f.line_count(), 4);
+        assert_eq!(f.position(0), Position { line: 1, column: 1 });
+        assert_eq!(f.position(3), Position { line: 2, column: 1 });
+        assert_eq!(f.position(7), Position { line: 2, column: 4 });
+        assert_eq!(f.position(9), Position { line: 4, column: 1 });
+        for off in [0, 1, 2, 3, 4, 5, 7, 8, 9, 10].iter() {
+            assert_eq!(f.offset(f.position(*off)), Some(*off));
+        }
+        assert_eq!(f.line(2), Some("cdé"));
+        assert_eq!(f.first_line((1, 5)), "b");
+        assert_eq!(f.offset(Position { line: 5, column: 1 }), None);
+        assert_eq!(format!("{}", f.location((4, 5))), "foo.pl:2:2");
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("a.pl".to_string(), "x\ny".to_string());
+        let b = sm.add_file("b.pl".to_string(), "zz".to_string());
+        assert_eq!(a, 0);
+        let (f, pos) = sm.lookup(b + 1).unwrap();
+        assert_eq!(f.name, "b.pl");
+        assert_eq!(pos, Position { line: 1, column: 2 });
+        assert_eq!(sm.offset("a.pl", Position { line: 2, column: 1 }), Some(2));
+        assert_eq!(format!("{}", sm.location((2, 3)).unwrap()), "a.pl:2:1");
+    }
+}
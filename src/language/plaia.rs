@@ -1,4 +1,13 @@
 pub mod interpret;
 pub mod concrete;
+pub mod trace;
+pub mod debug;
+pub mod profile;
+pub mod error;
 pub mod signed;
 pub mod abstraction;
+pub mod analysis;
+pub mod constant;
+pub mod interval;
+pub mod parity;
+pub mod congruence;
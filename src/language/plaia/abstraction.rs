@@ -0,0 +1,684 @@
+use crate::language::plaia::concrete::ValCompute;
+use crate::language::plaia::error::RuntimeErrorKind;
+use plaia_language::language::plaia::ast::*;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
+
+// A complete lattice of abstract properties, ordered by precision: `leq(a, b)`
+// means that `a` describes no more concrete values than `b` does.
+pub trait Lattice: Clone + PartialEq + Debug {
+    // No value at all (e.g. an unreachable program point)
+    fn bottom() -> Self;
+    // Any value
+    fn top() -> Self;
+    fn join(&self, other: &Self) -> Self;
+    fn meet(&self, other: &Self) -> Self;
+    fn leq(&self, other: &Self) -> bool;
+
+    fn is_bottom(&self) -> bool {
+        self.leq(&Self::bottom())
+    }
+
+    // An upper bound of `self` and `other` such that any chain
+    // `x0, x0.widen(x1), ...` stabilizes. The join is enough for lattices
+    // without infinite ascending chains.
+    fn widen(&self, other: &Self) -> Self {
+        self.join(other)
+    }
+
+    // Given `other` below `self`, something in between that any chain
+    // `x0, x0.narrow(x1), ...` reaches in finitely many steps. Used to
+    // recover precision lost by widening.
+    fn narrow(&self, other: &Self) -> Self {
+        self.meet(other)
+    }
+}
+
+// The two point lattice `false <= true`, e.g. whether a point is reachable
+impl Lattice for bool {
+    fn bottom() -> Self {
+        false
+    }
+
+    fn top() -> Self {
+        true
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        *self || *other
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        *self && *other
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        !*self || *other
+    }
+}
+
+// Values of an abstract domain. `ValCompute` gives the operations that are
+// exact on the abstraction (building tuples, vectors and pointers, and an
+// `op` that fails when no concrete operation can succeed); the transfer
+// functions below over-approximate the concrete semantics instead: the
+// result of `abs_op` describes every value that `op` may produce on values
+// described by the operands.
+pub trait AbstractValue<L>: Lattice + ValCompute<L> {
+    fn abs_lit(l: &Lit) -> Self {
+        Self::from_lit(l)
+    }
+
+    fn abs_op(o: &BinOp, v1: &Self, v2: &Self) -> Self;
+
+    // An error that `o` may raise on some values described by `v1` and `v2`
+    fn op_alarm(_o: &BinOp, _v1: &Self, _v2: &Self) -> Option<RuntimeErrorKind> {
+        None
+    }
+
+    // As `op_alarm`, for the unary operator `o`
+    fn unop_alarm(_o: &UnOp, _v: &Self) -> Option<RuntimeErrorKind> {
+        None
+    }
+
+    // Whether the guard `v` may evaluate to `b`
+    fn may_be(v: &Self, b: bool) -> bool;
+
+    // Refine the operands of the comparison `v1 o v2` knowing that it
+    // evaluated to `b`. Either result is bottom if that is impossible; the
+    // default keeps the operands as they are.
+    fn assume(_o: &BinOp, v1: &Self, v2: &Self, _b: bool) -> (Self, Self) {
+        (v1.clone(), v2.clone())
+    }
+
+    // The addresses a pointer may hold, or `None` if it may hold any
+    fn addrs(v: &Self) -> Option<Vec<L>>;
+
+    // A vector of any integers, of any length, such as `main`'s arguments
+    fn any_vector() -> Self;
+}
+
+// Abstract heap addresses, one per allocation site and calling context
+pub type AbsAddr = usize;
+
+// An abstraction of sets of integers, which `AbsValue` lifts to pointers,
+// tuples and vectors. Booleans are the integers 1 (true) and 0 (false).
+pub trait NumDomain: Lattice + fmt::Display {
+    fn constant(n: i64) -> Self;
+
+    // `+`, `-`, `*` and `/`, where division only has to account for the
+    // nonzero divisors
+    fn arith(o: &BinOp, a: &Self, b: &Self) -> Self;
+
+    fn neg(a: &Self) -> Self {
+        Self::arith(&BinOp::Sub, &Self::constant(0), a)
+    }
+
+    // As `AbstractValue::assume`, for the comparison operators. Only `==`
+    // (and a failed `!=`) is refined by default.
+    fn assume(o: &BinOp, a: &Self, b: &Self, holds: bool) -> (Self, Self) {
+        match (o, holds) {
+            (BinOp::Eq, true) | (BinOp::Neq, false) => {
+                let m = a.meet(b);
+                (m.clone(), m)
+            }
+            _ => (a.clone(), b.clone()),
+        }
+    }
+
+    // Whether `a o b` may not fit in an i64. By default, from the signs the
+    // operands may have and, when one is a constant, whether the other may
+    // be outside the range where the result fits.
+    fn may_overflow(o: &BinOp, a: &Self, b: &Self) -> bool {
+        if a.is_bottom() || b.is_bottom() {
+            return false;
+        }
+        let may = |x: &Self, o: BinOp, n: i64| !Self::assume(&o, x, &Self::constant(n), true).0.is_bottom();
+        let signs = match o {
+            BinOp::Add => (may(a, BinOp::Gt, 0) && may(b, BinOp::Gt, 0))
+                          || (may(a, BinOp::Lt, 0) && may(b, BinOp::Lt, 0)),
+            BinOp::Sub => (may(a, BinOp::Gte, 0) && may(b, BinOp::Lt, 0))
+                          || (may(a, BinOp::Lt, 0) && may(b, BinOp::Gt, 0)),
+            BinOp::Mul => {
+                let large = |x: &Self| may(x, BinOp::Lt, -1) || may(x, BinOp::Gt, 1);
+                (large(a) && large(b)) || (a.contains(i64::MIN) && b.contains(-1))
+                    || (a.contains(-1) && b.contains(i64::MIN))
+            }
+            // Only `i64::MIN / -1` overflows
+            _ => a.contains(i64::MIN) && b.contains(-1),
+        };
+        let (min, max) = (i64::MIN as i128, i64::MAX as i128);
+        signs && match (o, a.as_constant(), b.as_constant()) {
+            (BinOp::Div, _, Some(0)) => false,
+            (o, Some(x), Some(y)) => arith_const(o, x, y).is_none(),
+            (BinOp::Add, Some(c), _) => may_leave(b, min - c as i128, max - c as i128),
+            (BinOp::Add, _, Some(c)) => may_leave(a, min - c as i128, max - c as i128),
+            (BinOp::Sub, _, Some(c)) => may_leave(a, min + c as i128, max + c as i128),
+            (BinOp::Sub, Some(c), _) => may_leave(b, c as i128 - max, c as i128 - min),
+            (BinOp::Mul, Some(0), _) | (BinOp::Mul, _, Some(0)) => false,
+            (BinOp::Mul, Some(c), _) | (BinOp::Mul, _, Some(c)) => {
+                let x = if a.as_constant().is_some() { b } else { a };
+                let m = (c as i128).abs();
+                if c > 0 { may_leave(x, -(-min / m), max / m) } else { may_leave(x, -(max / m), -min / m) }
+            }
+            _ => true,
+        }
+    }
+
+    // The only integer described, if there is exactly one
+    fn as_constant(&self) -> Option<i64> {
+        None
+    }
+
+    fn contains(&self, n: i64) -> bool {
+        !self.meet(&Self::constant(n)).is_bottom()
+    }
+
+    fn non_negative() -> Self {
+        Self::assume(&BinOp::Gte, &Self::top(), &Self::constant(0), true).0
+    }
+}
+
+// A numeric domain lifted to every kind of value. Values of different kinds
+// (which a well typed program never mixes) only have `Top` as upper bound.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbsValue<N> {
+    Bottom,
+    Num(N),
+    Addr(BTreeSet<AbsAddr>),
+    Tuple(Vec<AbsValue<N>>),
+    // The join of the elements, and the length
+    Vector(Box<AbsValue<N>>, N),
+    Top,
+}
+
+use AbsValue::*;
+
+fn is_comparison(o: &BinOp) -> bool {
+    matches!(o, BinOp::Lt | BinOp::Gt | BinOp::Lte | BinOp::Gte | BinOp::Eq | BinOp::Neq)
+}
+
+// `x o' y` whenever not `x o y`, for comparisons `o`
+pub fn negation(o: &BinOp) -> BinOp {
+    match o {
+        BinOp::Lt => BinOp::Gte,
+        BinOp::Gte => BinOp::Lt,
+        BinOp::Gt => BinOp::Lte,
+        BinOp::Lte => BinOp::Gt,
+        BinOp::Eq => BinOp::Neq,
+        BinOp::Neq => BinOp::Eq,
+        o => o.clone(),
+    }
+}
+
+// `y o' x` whenever `x o y`
+pub fn converse(o: &BinOp) -> BinOp {
+    match o {
+        BinOp::Lt => BinOp::Gt,
+        BinOp::Gt => BinOp::Lt,
+        BinOp::Lte => BinOp::Gte,
+        BinOp::Gte => BinOp::Lte,
+        o => o.clone(),
+    }
+}
+
+// Whether `a` may be below `lo` or above `hi`, which need not be i64s
+fn may_leave<N: NumDomain>(a: &N, lo: i128, hi: i128) -> bool {
+    // Bounds beyond the i64s are either met by every i64 or by none
+    let beyond = |o: BinOp, bound: i128| match i64::try_from(bound) {
+        Ok(n) => !N::assume(&o, a, &N::constant(n), true).0.is_bottom(),
+        Err(_) => true,
+    };
+    (lo > i64::MIN as i128 && beyond(BinOp::Lt, lo)) || (hi < i64::MAX as i128 && beyond(BinOp::Gt, hi))
+}
+
+// `x o y` for the arithmetic operators, if it neither overflows nor divides
+// by zero
+pub fn arith_const(o: &BinOp, x: i64, y: i64) -> Option<i64> {
+    match o {
+        BinOp::Add => x.checked_add(y),
+        BinOp::Sub => x.checked_sub(y),
+        BinOp::Mul => x.checked_mul(y),
+        BinOp::Div => x.checked_div(y),
+        o => panic!("{:?} is not an arithmetic operator", o),
+    }
+}
+
+// `x o y` for comparisons `o`
+pub fn compare_const(o: &BinOp, x: i64, y: i64) -> bool {
+    match o {
+        BinOp::Lt => x < y,
+        BinOp::Gt => x > y,
+        BinOp::Lte => x <= y,
+        BinOp::Gte => x >= y,
+        BinOp::Eq => x == y,
+        BinOp::Neq => x != y,
+        o => panic!("{:?} is not a comparison", o),
+    }
+}
+
+impl<N: NumDomain> AbsValue<N> {
+    pub fn num(n: N) -> Self {
+        if n.is_bottom() { Bottom } else { Num(n) }
+    }
+
+    // Values that only numbers are used as (operands of arithmetic and
+    // comparisons, and indices), where `Top` has to be a number
+    fn as_num(&self) -> Option<N> {
+        match self {
+            Num(n) => Some(n.clone()),
+            Top => Some(N::top()),
+            _ => None,
+        }
+    }
+
+    // The booleans: true if `t`, false if `f`
+    fn truth(t: bool, f: bool) -> Self {
+        let mut n = N::bottom();
+        if t {
+            n = n.join(&N::constant(1));
+        }
+        if f {
+            n = n.join(&N::constant(0));
+        }
+        Self::num(n)
+    }
+
+    // Whether the index `i` may be below 0, or at least `len`
+    fn may_be_outside(i: &N, len: &N) -> bool {
+        let below = N::assume(&BinOp::Lt, i, &N::constant(0), true).0;
+        let (above, _) = N::assume(&BinOp::Gte, i, len, true);
+        !below.is_bottom() || !above.is_bottom()
+    }
+
+    fn may_be_inside(i: &N, len: &N) -> bool {
+        let (i, _) = N::assume(&BinOp::Gte, i, &N::constant(0), true);
+        !N::assume(&BinOp::Lt, &i, len, true).0.is_bottom()
+    }
+
+    // Combine values of the same kind with `num` on numbers and `val` on
+    // their components
+    fn upper(&self, other: &Self, num: &dyn Fn(&N, &N) -> N, val: &dyn Fn(&Self, &Self) -> Self) -> Self {
+        match (self, other) {
+            (Bottom, v) | (v, Bottom) => v.clone(),
+            (Num(a), Num(b)) => Num(num(a, b)),
+            (Addr(a), Addr(b)) => Addr(a.union(b).copied().collect()),
+            (Tuple(vs), Tuple(ws)) if vs.len() == ws.len() => {
+                Tuple(vs.iter().zip(ws).map(|(v, w)| val(v, w)).collect())
+            }
+            (Vector(e1, l1), Vector(e2, l2)) => Vector(Box::new(val(e1, e2)), num(l1, l2)),
+            _ => Top,
+        }
+    }
+
+    fn lower(&self, other: &Self, num: &dyn Fn(&N, &N) -> N, val: &dyn Fn(&Self, &Self) -> Self) -> Self {
+        match (self, other) {
+            (Top, v) | (v, Top) => v.clone(),
+            (Num(a), Num(b)) => Self::num(num(a, b)),
+            (Addr(a), Addr(b)) => {
+                let common: BTreeSet<_> = a.intersection(b).copied().collect();
+                if common.is_empty() { Bottom } else { Addr(common) }
+            }
+            (Tuple(vs), Tuple(ws)) if vs.len() == ws.len() => {
+                let us: Vec<_> = vs.iter().zip(ws).map(|(v, w)| val(v, w)).collect();
+                if us.iter().any(|u| u.is_bottom()) { Bottom } else { Tuple(us) }
+            }
+            (Vector(e1, l1), Vector(e2, l2)) => {
+                let len = num(l1, l2);
+                if len.is_bottom() { Bottom } else { Vector(Box::new(val(e1, e2)), len) }
+            }
+            _ => Bottom,
+        }
+    }
+}
+
+impl<N: NumDomain> Lattice for AbsValue<N> {
+    fn bottom() -> Self {
+        Bottom
+    }
+
+    fn top() -> Self {
+        Top
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        self.upper(other, &|a, b| a.join(b), &|v, w| v.join(w))
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        self.lower(other, &|a, b| a.meet(b), &|v, w| v.meet(w))
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Bottom, _) | (_, Top) => true,
+            (Num(a), Num(b)) => a.leq(b),
+            (Addr(a), Addr(b)) => a.is_subset(b),
+            (Tuple(vs), Tuple(ws)) => vs.len() == ws.len() && vs.iter().zip(ws).all(|(v, w)| v.leq(w)),
+            (Vector(e1, l1), Vector(e2, l2)) => e1.leq(e2) && l1.leq(l2),
+            _ => false,
+        }
+    }
+
+    fn widen(&self, other: &Self) -> Self {
+        self.upper(other, &|a, b| a.widen(b), &|v, w| v.widen(w))
+    }
+
+    fn narrow(&self, other: &Self) -> Self {
+        self.lower(other, &|a, b| a.narrow(b), &|v, w| v.narrow(w))
+    }
+}
+
+impl<N: NumDomain> ValCompute<AbsAddr> for AbsValue<N> {
+    fn zero() -> Self {
+        Num(N::constant(0))
+    }
+
+    // Definitely true
+    fn is_true(v: &Self) -> bool {
+        Self::may_be(v, true) && !Self::may_be(v, false)
+    }
+
+    fn op(o: &BinOp, e1: Self, e2: Self) -> Result<Self, RuntimeErrorKind> {
+        let v = Self::abs_op(o, &e1, &e2);
+        if v.is_bottom() && !e1.is_bottom() && !e2.is_bottom() {
+            Err(Self::op_alarm(o, &e1, &e2).unwrap_or_else(|| {
+                RuntimeErrorKind::TypeError(format!("cannot apply {:?} to {} and {}", o, e1, e2))
+            }))
+        } else {
+            Ok(v)
+        }
+    }
+
+    fn unop(o: &UnOp, e: Self) -> Result<Self, RuntimeErrorKind> {
+        let v = match (o, &e) {
+            (_, Bottom) => Bottom,
+            (UnOp::Negate, _) => Self::truth(Self::may_be(&e, false), Self::may_be(&e, true)),
+            (UnOp::Minus, Num(n)) => Self::num(N::neg(n)),
+            (UnOp::Minus, Top) => Num(N::top()),
+            (UnOp::Len, Tuple(vs)) => Num(N::constant(vs.len() as i64)),
+            (UnOp::Len, Vector(_, len)) => Num(len.clone()),
+            (UnOp::Len, Top) => Num(N::non_negative()),
+            _ => Bottom,
+        };
+        if v.is_bottom() && !e.is_bottom() {
+            Err(Self::unop_alarm(o, &e).unwrap_or_else(|| {
+                RuntimeErrorKind::TypeError(format!("cannot apply {:?} to {}", o, e))
+            }))
+        } else {
+            Ok(v)
+        }
+    }
+
+    // Writing to an index that may be one of several positions of a tuple,
+    // or to a vector, keeps the old values as well
+    fn update(agg: Self, i: Self, v: Self) -> Result<Self, RuntimeErrorKind> {
+        match (agg, i.as_num()) {
+            (Bottom, _) => Ok(Bottom),
+            (_, Some(n)) if n.is_bottom() => Ok(Bottom),
+            (Tuple(mut vs), Some(n)) => {
+                let hits: Vec<usize> = (0..vs.len()).filter(|k| n.contains(*k as i64)).collect();
+                match hits[..] {
+                    [] => return Err(RuntimeErrorKind::IndexMayBeOutOfBounds {
+                        index: n.to_string(),
+                        len: vs.len().to_string(),
+                    }),
+                    [k] if n.as_constant() == Some(k as i64) => vs[k] = v,
+                    _ => for k in hits {
+                        vs[k] = vs[k].join(&v);
+                    },
+                }
+                Ok(Tuple(vs))
+            }
+            (Vector(e, len), Some(_)) => Ok(Vector(Box::new(e.join(&v)), len)),
+            (Top, _) => Ok(Top),
+            (agg, _) => Err(RuntimeErrorKind::TypeError(format!("cannot index {} with {}", agg, i))),
+        }
+    }
+
+    fn push(agg: Self, v: Self) -> Result<Self, RuntimeErrorKind> {
+        match agg {
+            Bottom => Ok(Bottom),
+            Vector(e, len) => Ok(Vector(Box::new(e.join(&v)), N::arith(&BinOp::Add, &len, &N::constant(1)))),
+            Top => Ok(Top),
+            agg => Err(RuntimeErrorKind::TypeError(format!("cannot push onto {}", agg))),
+        }
+    }
+
+    fn from_lit(l: &Lit) -> Self {
+        match l.lit {
+            LiteralKind::LInt(n) => Num(N::constant(n)),
+            LiteralKind::LBool(b) => Num(N::constant(if b { 1 } else { 0 })),
+        }
+    }
+
+    fn from_loc(l: &AbsAddr) -> Self {
+        Addr(std::iter::once(*l).collect())
+    }
+
+    fn from_tuple(vs: Vec<Self>) -> Self {
+        Tuple(vs)
+    }
+
+    fn from_vector(vs: Vec<Self>) -> Self {
+        let len = N::constant(vs.len() as i64);
+        Vector(Box::new(vs.iter().fold(Bottom, |e, v| e.join(v))), len)
+    }
+
+    fn unwrap_loc(v: Self) -> Result<AbsAddr, RuntimeErrorKind> {
+        match &v {
+            Addr(ls) if ls.len() == 1 => Ok(*ls.iter().next().unwrap()),
+            _ => Err(RuntimeErrorKind::NotAPointer(v.to_string())),
+        }
+    }
+}
+
+impl<N: NumDomain> AbstractValue<AbsAddr> for AbsValue<N> {
+    fn abs_op(o: &BinOp, v1: &Self, v2: &Self) -> Self {
+        match (o, v1, v2) {
+            (_, Bottom, _) | (_, _, Bottom) => Bottom,
+            (BinOp::Proj, Tuple(vs), _) => match v2.as_num() {
+                Some(i) => vs.iter().enumerate()
+                             .filter(|(k, _)| i.contains(*k as i64))
+                             .fold(Bottom, |r, (_, v)| r.join(v)),
+                None => Bottom,
+            },
+            (BinOp::Proj, Vector(e, len), _) => match v2.as_num() {
+                Some(i) if Self::may_be_inside(&i, len) => (**e).clone(),
+                _ => Bottom,
+            },
+            (BinOp::Proj, Top, _) => Top,
+            (BinOp::And, _, _) => Self::truth(Self::may_be(v1, true) && Self::may_be(v2, true),
+                                              Self::may_be(v1, false) || Self::may_be(v2, false)),
+            (BinOp::Or, _, _) => Self::truth(Self::may_be(v1, true) || Self::may_be(v2, true),
+                                             Self::may_be(v1, false) && Self::may_be(v2, false)),
+            (o, Num(a), Num(b)) if is_comparison(o) => {
+                let feasible = |holds| {
+                    let (a, b) = N::assume(o, a, b, holds);
+                    !a.is_bottom() && !b.is_bottom()
+                };
+                Self::truth(feasible(true), feasible(false))
+            }
+            (BinOp::Eq, Addr(a), Addr(b)) | (BinOp::Neq, Addr(a), Addr(b)) => {
+                // One address may stand for several cells
+                let (may_eq, may_ne) = (!a.is_disjoint(b), true);
+                if *o == BinOp::Eq { Self::truth(may_eq, may_ne) } else { Self::truth(may_ne, may_eq) }
+            }
+            (o, Top, _) | (o, _, Top) if is_comparison(o) => Self::truth(true, true),
+            (BinOp::Add, _, _) | (BinOp::Sub, _, _) | (BinOp::Mul, _, _) | (BinOp::Div, _, _) => {
+                match (v1.as_num(), v2.as_num()) {
+                    (Some(a), Some(b)) => Self::num(N::arith(o, &a, &b)),
+                    _ => Bottom,
+                }
+            }
+            _ => Bottom,
+        }
+    }
+
+    fn op_alarm(o: &BinOp, v1: &Self, v2: &Self) -> Option<RuntimeErrorKind> {
+        let arith = matches!(o, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div);
+        match (o, v1, &v2.as_num()) {
+            (BinOp::Div, _, Some(b)) if b.contains(0) => Some(RuntimeErrorKind::DivisionByZero),
+            (o, a, Some(b)) if arith && a.as_num().is_some_and(|a| N::may_overflow(o, &a, b)) => {
+                Some(RuntimeErrorKind::Overflow)
+            }
+            (BinOp::Proj, Tuple(vs), Some(i)) if Self::may_be_outside(i, &N::constant(vs.len() as i64)) => {
+                Some(RuntimeErrorKind::IndexMayBeOutOfBounds { index: i.to_string(), len: vs.len().to_string() })
+            }
+            (BinOp::Proj, Vector(_, len), Some(i)) if Self::may_be_outside(i, len) => {
+                Some(RuntimeErrorKind::IndexMayBeOutOfBounds { index: i.to_string(), len: len.to_string() })
+            }
+            // Nothing is known about the length
+            (BinOp::Proj, Top, Some(i)) => {
+                Some(RuntimeErrorKind::IndexMayBeOutOfBounds { index: i.to_string(), len: N::non_negative().to_string() })
+            }
+            _ => None,
+        }
+    }
+
+    // `-n` is `0 - n`
+    fn unop_alarm(o: &UnOp, v: &Self) -> Option<RuntimeErrorKind> {
+        match (o, v.as_num()) {
+            (UnOp::Minus, Some(n)) if N::may_overflow(&BinOp::Sub, &N::constant(0), &n) => {
+                Some(RuntimeErrorKind::Overflow)
+            }
+            _ => None,
+        }
+    }
+
+    fn may_be(v: &Self, b: bool) -> bool {
+        match v {
+            Bottom => false,
+            Num(n) if b => n.contains(1),
+            Num(n) => n.as_constant() != Some(1),
+            _ => true,
+        }
+    }
+
+    fn assume(o: &BinOp, v1: &Self, v2: &Self, b: bool) -> (Self, Self) {
+        match (v1.as_num(), v2.as_num()) {
+            (Some(a), Some(c)) if is_comparison(o) => {
+                let (a, c) = N::assume(o, &a, &c, b);
+                (Self::num(a), Self::num(c))
+            }
+            _ => (v1.clone(), v2.clone()),
+        }
+    }
+
+    fn addrs(v: &Self) -> Option<Vec<AbsAddr>> {
+        match v {
+            Addr(ls) => Some(ls.iter().copied().collect()),
+            Top => None,
+            _ => Some(Vec::new()),
+        }
+    }
+
+    fn any_vector() -> Self {
+        Vector(Box::new(Num(N::top())), N::non_negative())
+    }
+}
+
+impl<N: fmt::Display> fmt::Display for AbsValue<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bottom => write!(f, "⊥"),
+            Num(n) => write!(f, "{}", n),
+            Addr(ls) => {
+                let ls: Vec<String> = ls.iter().map(|l| format!("&{}", l)).collect();
+                write!(f, "{}", ls.join("|"))
+            }
+            Tuple(vs) => {
+                let vs: Vec<String> = vs.iter().map(|v| v.to_string()).collect();
+                write!(f, "({})", vs.join(", "))
+            }
+            Vector(e, len) => write!(f, "[{}; {}]", e, len),
+            Top => write!(f, "⊤"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::language::plaia::abstraction::*;
+
+    // Check the lattice laws on every combination of `xs`
+    pub fn check_laws<T: Lattice>(xs: &[T]) {
+        let (bot, top) = (T::bottom(), T::top());
+        assert!(bot.is_bottom());
+        for a in xs {
+            assert!(bot.leq(a) && a.leq(&top), "{:?} is not between bottom and top", a);
+            assert_eq!(a.join(a), *a);
+            assert_eq!(a.meet(a), *a);
+            for b in xs {
+                let (j, m) = (a.join(b), a.meet(b));
+                assert_eq!(j, b.join(a));
+                assert_eq!(m, b.meet(a));
+                assert!(a.leq(&j) && b.leq(&j), "{:?} join {:?} = {:?} is not an upper bound", a, b, j);
+                assert!(m.leq(a) && m.leq(b), "{:?} meet {:?} = {:?} is not a lower bound", a, b, m);
+                assert_eq!(a.leq(b), j == *b, "{:?} <= {:?} disagrees with the join", a, b);
+                let w = a.widen(b);
+                assert!(a.leq(&w) && b.leq(&w), "{:?} widen {:?} = {:?} is not an upper bound", a, b, w);
+                if b.leq(a) {
+                    let n = a.narrow(b);
+                    assert!(b.leq(&n) && n.leq(a), "{:?} narrow {:?} = {:?} is out of range", a, b, n);
+                }
+            }
+        }
+    }
+
+    // Check that `abs_op`, `op_alarm`, `assume` and the unary operators on
+    // numbers account for what the concrete operations do on every `n` in
+    // `ns`, or at the ends of the i64s, described by the operands, for every
+    // combination of `xs`
+    pub fn check_sound<N: NumDomain>(xs: &[N], ns: impl IntoIterator<Item = i64>) {
+        use crate::language::plaia::concrete::SimpleValue;
+        use BinOp::*;
+        let bounds = [i64::MIN, i64::MIN + 1, i64::MAX - 1, i64::MAX];
+        let ns: Vec<i64> = ns.into_iter().chain(bounds.iter().copied()).collect();
+        for a in xs {
+            for b in xs {
+                let (va, vb) = (AbsValue::Num(a.clone()), AbsValue::Num(b.clone()));
+                for o in &[Add, Sub, Mul, Div, Eq, Neq, Lt, Gt, Lte, Gte, And, Or] {
+                    let r = AbsValue::abs_op(o, &va, &vb);
+                    for &x in ns.iter().filter(|x| a.contains(**x)) {
+                        for &y in ns.iter().filter(|y| b.contains(**y)) {
+                            match SimpleValue::op(o, SimpleValue::VInt(x), SimpleValue::VInt(y)) {
+                                Ok(SimpleValue::VInt(z)) => {
+                                    assert!(AbsValue::Num(N::constant(z)).leq(&r),
+                                            "{} {:?} {} = {} is not in {} {:?} {} = {}", x, o, y, z, a, o, b, r);
+                                    if is_comparison(o) {
+                                        let (a2, b2) = AbsValue::assume(o, &va, &vb, z == 1);
+                                        assert!(AbsValue::Num(N::constant(x)).leq(&a2)
+                                                && AbsValue::Num(N::constant(y)).leq(&b2),
+                                                "assuming {} {:?} {} is {} loses {} {:?} {}", a, o, b, z == 1, x, o, y);
+                                    }
+                                }
+                                Ok(v) => panic!("{:?} is not a number", v),
+                                Err(_) => assert!(AbsValue::op_alarm(o, &va, &vb).is_some(),
+                                                  "no alarm for {} {:?} {}", x, o, y),
+                            }
+                        }
+                    }
+                }
+            }
+            let va = AbsValue::Num(a.clone());
+            for o in &[UnOp::Minus, UnOp::Negate] {
+                let r = AbsValue::unop(o, va.clone());
+                for &x in ns.iter().filter(|x| a.contains(**x)) {
+                    match SimpleValue::unop(o, SimpleValue::VInt(x)) {
+                        Ok(SimpleValue::VInt(z)) => assert!(r.as_ref().is_ok_and(|r| AbsValue::Num(N::constant(z)).leq(r)),
+                                                            "{:?} {} = {} is not in {:?} {} = {:?}", o, x, z, o, a, r),
+                        Ok(v) => panic!("{:?} is not a number", v),
+                        Err(_) => assert!(AbsValue::unop_alarm(o, &va).is_some(), "no alarm for {:?} {}", o, x),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bool() {
+        check_laws(&[false, true]);
+        assert!(!true.leq(&false));
+    }
+}
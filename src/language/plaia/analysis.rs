@@ -0,0 +1,991 @@
+use crate::language::plaia::abstraction::*;
+use crate::language::plaia::concrete::{frame_scopes, Shadowed};
+use crate::language::plaia::error::*;
+use crate::language::plaia::interpret::*;
+use plaia_language::language::plaia::ast::*;
+use plaia_language::language::plaia::source::SourceFile;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+pub type AbsStore = HashMap<Symbol, AbsAddr>;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Frame {
+    f: Symbol,
+    // The calls that led to this frame, outermost first
+    context: Vec<Loc>,
+    store: AbsStore,
+    scopes: Vec<Shadowed>,
+    ret: AbsAddr,
+    // The statement being run and how many cells it has allocated so far,
+    // which together with the context name an allocation site
+    stmt: Loc,
+    allocs: usize,
+    // A call to a function that was already active, see `abstract_stmt_cb`.
+    // All of those share the context of the outermost activation followed
+    // by the location of the function.
+    recursive: bool,
+}
+
+// Everything an abstract evaluator knows at a program point. Two states at
+// the same point and context only differ in their heaps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbsState<V> {
+    heap: BTreeMap<AbsAddr, V>,
+    globals: AbsStore,
+    frames: Vec<Frame>,
+    // As in `ConcreteEvaluator`, for reporting alarms
+    locs: Vec<Loc>,
+}
+
+impl<V: Lattice> AbsState<V> {
+    // Combine the heaps of `self` and `other` with `f`
+    fn combine(&self, other: &Self, f: impl Fn(&V, &V) -> V) -> Self {
+        let mut s = self.clone();
+        for (l, v) in &other.heap {
+            let w = match self.heap.get(l) {
+                Some(u) => f(u, v),
+                None => v.clone(),
+            };
+            s.heap.insert(*l, w);
+        }
+        s
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        self.heap.iter().all(|(l, v)| other.heap.get(l).map_or(v.is_bottom(), |w| v.leq(w)))
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("no frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no frame")
+    }
+
+    fn lookup(&self, x: &Symbol) -> Option<AbsAddr> {
+        self.frame().store.get(x).or_else(|| self.globals.get(x)).copied()
+    }
+
+    // The variables in scope and their values
+    pub fn vars(&self) -> BTreeMap<String, V> {
+        self.globals.iter().chain(&self.frame().store)
+            .map(|(x, l)| (x.name.clone(), self.heap.get(l).cloned().unwrap_or_else(V::bottom)))
+            .collect()
+    }
+}
+
+// Runs every path through a module at once, in the value domain `V`. The
+// state on entry to each statement is recorded per calling context; a path
+// that reaches a statement with nothing new is dropped, and one that does
+// continues with the join of the two.
+pub struct AbstractEvaluator<'a, V> {
+    state: AbsState<V>,
+    decls: HashMap<Symbol, &'a FnDecl>,
+    sites: HashMap<(Vec<Loc>, Loc, usize), AbsAddr>,
+    // Allocated more than once on some path, so they may stand for several
+    // cells. Of those, the ones whose address was taken get weak updates.
+    summaries: HashSet<AbsAddr>,
+    escaped: RefCell<HashSet<AbsAddr>>,
+    points: HashMap<(Loc, Vec<Loc>), AbsState<V>>,
+    heads: HashMap<(Loc, Vec<Loc>), AbsState<V>>,
+    // Once the loop heads are stable, each pass keeps them fixed, runs each
+    // loop body once from them and collects what arrives at them, which they
+    // are narrowed with for the next pass
+    narrowing: bool,
+    arrivals: HashMap<(Loc, Vec<Loc>), AbsState<V>>,
+    explored: HashSet<(Loc, Vec<Loc>)>,
+    // The state when `main` returns
+    exit: Option<AbsState<V>>,
+    alarms: Vec<(Loc, RuntimeErrorKind)>,
+    indices: BTreeMap<Loc, V>,
+}
+
+pub type AbstractResult<'a, V> = Bounce<'a, AbstractEvaluator<'a, V>, ()>;
+
+impl<'a, V: AbstractValue<AbsAddr> + 'a> AbstractEvaluator<'a, V> {
+    fn alloc_site(&mut self) -> AbsAddr {
+        let frame = self.state.frame_mut();
+        let site = (frame.context.clone(), frame.stmt, frame.allocs);
+        frame.allocs += 1;
+        let fresh = self.sites.len();
+        let l = *self.sites.entry(site).or_insert(fresh);
+        if self.state.heap.contains_key(&l) {
+            self.summaries.insert(l);
+        }
+        l
+    }
+
+    fn weak(&self, l: &AbsAddr) -> bool {
+        self.summaries.contains(l) && self.escaped.borrow().contains(l)
+    }
+
+    fn write(&mut self, l: AbsAddr, v: V) {
+        let v = match self.state.heap.get(&l) {
+            Some(old) if self.weak(&l) => old.join(&v),
+            _ => v,
+        };
+        self.state.heap.insert(l, v);
+    }
+
+    fn bind(&mut self, x: &Symbol, l: AbsAddr) {
+        let frame = self.state.frame_mut();
+        let old = frame.store.insert(x.clone(), l);
+        if frame.scopes.len() > 1 {
+            frame.scopes.last_mut().unwrap().push((x.clone(), old));
+        }
+    }
+
+    fn alarm(&mut self, err: RuntimeErrorKind) {
+        let alarm = (self.state.locs.last().copied().unwrap_or((0, 0)), err);
+        if !self.alarms.contains(&alarm) {
+            self.alarms.push(alarm);
+        }
+    }
+
+    // Note an index into a tuple or vector at the current expression
+    fn index(&mut self, i: &V) {
+        let loc = self.state.locs.last().copied().unwrap_or((0, 0));
+        let v = self.indices.get(&loc).map_or(i.clone(), |w| w.join(i));
+        self.indices.insert(loc, v);
+    }
+
+    // Continue with the result of a `ValCompute` operation, or give up on
+    // this path if no concrete run can get past it
+    fn with_result<T, K>(&mut self, r: Result<T, RuntimeErrorKind>, k: &K) -> AbstractResult<'a, V>
+    where
+        K: ?Sized + Fn(&mut Self, T) -> AbstractResult<'a, V>
+    {
+        match r {
+            Ok(v) => k(self, v),
+            Err(e) => {
+                self.alarm(e);
+                Bounce::Done(())
+            }
+        }
+    }
+
+    // Run `k` on each of `choices`, starting from the current state each
+    // time. Each path is only taken up to where it first suspends, and the
+    // rest of it is left to the driver, so that however many paths are
+    // pending the native stack stays shallow.
+    fn fork<T, K>(&mut self, choices: Vec<T>, k: &K) -> AbstractResult<'a, V>
+    where
+        K: ?Sized + Fn(&mut Self, T) -> AbstractResult<'a, V>
+    {
+        let saved = self.state.clone();
+        let mut paths = Vec::new();
+        for c in choices {
+            self.state = saved.clone();
+            paths.push(match k(self, c) {
+                Bounce::More(next) => {
+                    let state = self.state.clone();
+                    Bounce::More(Box::new(move |eval: &mut Self| {
+                        eval.state = state;
+                        next(eval)
+                    }))
+                }
+                path => path,
+            });
+        }
+        let last = paths.pop().unwrap_or(Bounce::Done(()));
+        paths.into_iter().rev().fold(last, |rest, path| Bounce::Then(Box::new(path), Box::new(rest)))
+    }
+
+    // Join the current state into the one recorded for `key` (widening at
+    // loop heads) and continue with the result, unless nothing changed
+    fn record(&mut self, key: (Loc, Vec<Loc>), head: bool) -> bool {
+        let points = if head { &mut self.heads } else { &mut self.points };
+        let state = match points.get(&key) {
+            Some(old) if self.state.leq(old) => return false,
+            Some(old) if head => old.combine(&self.state, V::widen),
+            Some(old) => old.combine(&self.state, V::join),
+            None => self.state.clone(),
+        };
+        points.insert(key, state.clone());
+        self.state = state;
+        true
+    }
+
+    // Arrive at the head `key` (of a loop, or of the body of a recursive
+    // function) and say whether to go on from it, with the state there
+    fn enter_head(&mut self, key: (Loc, Vec<Loc>)) -> bool {
+        if self.narrowing {
+            let arrived = match self.arrivals.get(&key) {
+                Some(a) => a.combine(&self.state, V::join),
+                None => self.state.clone(),
+            };
+            self.arrivals.insert(key.clone(), arrived);
+            if let Some(head) = self.heads.get(&key).filter(|head| self.state.leq(head)) {
+                if !self.explored.insert(key) {
+                    return false;
+                }
+                self.state = head.clone();
+                return true;
+            }
+        }
+        self.record(key, true)
+    }
+
+    // Forget the heads of the loops inside the loop `s`, including those of
+    // functions called from it, so that each iteration of `s` stabilizes them
+    // afresh from what enters them. Otherwise widening at an inner head would
+    // also act on everything the outer loop changes.
+    fn restart_inner(&mut self, s: &Statement) {
+        let context = self.state.frame().context.clone();
+        let inside = |loc: &Loc| *loc != s.loc && s.loc.0 <= loc.0 && loc.1 <= s.loc.1;
+        self.heads.retain(|(loc, ctx), _| {
+            let nested = match ctx.get(context.len()) {
+                None => ctx == &context && inside(loc),
+                Some(call) => ctx.starts_with(&context) && inside(call),
+            };
+            !nested
+        });
+    }
+
+    // Forget what a call that was not analyzed may have changed: the globals,
+    // every cell whose address was taken, and the result
+    fn havoc(&mut self) {
+        let cells: Vec<AbsAddr> = self.state.globals.values().copied()
+                                      .chain(self.escaped.borrow().iter().copied())
+                                      .chain(std::iter::once(self.state.frame().ret))
+                                      .collect();
+        for l in cells {
+            self.state.heap.insert(l, V::top());
+        }
+    }
+
+    // The value of a variable or literal
+    fn operand(&self, e: &Expr) -> Option<V> {
+        match &e.expr {
+            ExprKind::Var(x) => self.state.lookup(x).and_then(|l| self.state.heap.get(&l).cloned()),
+            ExprKind::Lit(l) => Some(V::abs_lit(l)),
+            _ => None,
+        }
+    }
+
+    // Narrow down the value of a variable, unless its cell stands for others
+    fn assign(&mut self, e: &Expr, v: V) {
+        if let ExprKind::Var(x) = &e.expr {
+            if let Some(l) = self.state.lookup(x).filter(|l| !self.weak(l)) {
+                self.state.heap.insert(l, v);
+            }
+        }
+    }
+
+    // Assume that `guard` evaluated to `holds`, or say that it cannot have
+    fn refine(&mut self, guard: &Expr, holds: bool) -> bool {
+        match &guard.expr {
+            ExprKind::Unary(UnOp::Negate, e) => self.refine(e, !holds),
+            ExprKind::Binary(BinOp::And, l, r) if holds => self.refine(l, true) && self.refine(r, true),
+            ExprKind::Binary(BinOp::Or, l, r) if !holds => self.refine(l, false) && self.refine(r, false),
+            ExprKind::Binary(o, l, r) => self.refine_cmp(o, l, r, holds),
+            ExprKind::Var(_) => {
+                let one = Expr { expr: ExprKind::Lit(Lit { lit: LiteralKind::LInt(1), loc: guard.loc }), loc: guard.loc };
+                self.refine_cmp(&BinOp::Eq, guard, &one, holds)
+            }
+            _ => true,
+        }
+    }
+
+    fn refine_cmp(&mut self, o: &BinOp, l: &Expr, r: &Expr, holds: bool) -> bool {
+        match (self.operand(l), self.operand(r)) {
+            (Some(a), Some(b)) => {
+                let (a, b) = V::assume(o, &a, &b, holds);
+                if a.is_bottom() || b.is_bottom() {
+                    return false;
+                }
+                self.assign(l, a);
+                self.assign(r, b);
+                true
+            }
+            _ => true,
+        }
+    }
+
+    // Which of `true` and `false` `v` may be
+    fn outcomes(v: &V) -> Vec<bool> {
+        vec![true, false].into_iter().filter(|b| V::may_be(v, *b)).collect()
+    }
+}
+
+impl<'a, V> Evaluator<'a, AbstractResult<'a, V>> for AbstractEvaluator<'a, V>
+where
+    V: AbstractValue<AbsAddr> + 'a,
+{
+    type V = V;
+    type L = AbsAddr;
+
+    fn find_store<K>(&mut self, s: &Symbol, k: &K) -> AbstractResult<'a, V>
+    where K: ?Sized + Fn(&mut Self, Self::L) -> AbstractResult<'a, V>
+    {
+        let l = self.state.lookup(s).ok_or_else(|| RuntimeErrorKind::UnboundVariable(s.clone()));
+        self.with_result(l, k)
+    }
+
+    fn find_heap<K>(&mut self, s: &Self::L, k: &K) -> AbstractResult<'a, V>
+    where K: ?Sized + Fn(&mut Self, Self::V) -> AbstractResult<'a, V>
+    {
+        let v = self.state.heap.get(s).cloned().unwrap_or_else(V::bottom);
+        k(self, v)
+    }
+
+    fn alloc<K>(&mut self, k: &K) -> AbstractResult<'a, V>
+        where K: ?Sized + Fn(&mut Self, Self::L) -> AbstractResult<'a, V>
+    {
+        let l = self.alloc_site();
+        self.write(l, V::zero());
+        k(self, l)
+    }
+
+    fn update_store<K>(&mut self, x: &Symbol, l: &Self::L, k: &K) -> AbstractResult<'a, V>
+    where K: ?Sized + Fn(&mut Self) -> AbstractResult<'a, V>
+    {
+        self.bind(x, *l);
+        k(self)
+    }
+
+    fn update_global<K>(&mut self, x: &Symbol, l: &Self::L, k: &K) -> AbstractResult<'a, V>
+    where K: ?Sized + Fn(&mut Self) -> AbstractResult<'a, V>
+    {
+        self.state.globals.insert(x.clone(), *l);
+        k(self)
+    }
+
+    fn enter_scope(&mut self)
+    {
+        self.state.frame_mut().scopes.push(Vec::new());
+    }
+
+    fn exit_scope(&mut self)
+    {
+        let frame = self.state.frame_mut();
+        let shadowed = frame.scopes.pop().expect("no open scope");
+        for (x, old) in shadowed.into_iter().rev() {
+            match old {
+                Some(l) => frame.store.insert(x, l),
+                None => frame.store.remove(&x),
+            };
+        }
+    }
+
+    fn update_heap<K>(&mut self, l: &Self::L, v: Self::V, k: &K) -> AbstractResult<'a, V>
+    where K: ?Sized + Fn(&mut Self) -> AbstractResult<'a, V>
+    {
+        self.write(*l, v);
+        k(self)
+    }
+
+    fn push_frame<K>(&mut self, f: &Symbol, frame: Vec<(Symbol, Self::V)>, k: &K) -> AbstractResult<'a, V>
+    where K: ?Sized + Fn(&mut Self) -> AbstractResult<'a, V>
+    {
+        let decl = self.decls[f];
+        let (mut context, site) = match self.state.frames.iter().find(|fr| fr.f == *f) {
+            Some(outer) => (outer.context.clone(), decl.loc),
+            None => (self.state.frame().context.clone(), self.state.locs.last().copied().unwrap_or((0, 0))),
+        };
+        context.push(site);
+        let recursive = site == decl.loc;
+        self.state.frames.push(Frame {
+            f: f.clone(),
+            context,
+            store: HashMap::new(),
+            scopes: frame_scopes(&decl.body),
+            ret: 0,
+            stmt: decl.loc,
+            allocs: 0,
+            recursive,
+        });
+        for (x, v) in frame {
+            let l = self.alloc_site();
+            self.write(l, v);
+            self.bind(&x, l);
+        }
+        let ret = self.alloc_site();
+        self.write(ret, V::zero());
+        self.state.frame_mut().ret = ret;
+        k(self)
+    }
+
+    fn pop_frame(&mut self)
+    {
+        self.state.frames.pop();
+    }
+
+    fn return_loc(&mut self) -> Self::L
+    {
+        self.state.frame().ret
+    }
+
+    fn denote<K>(&mut self, o: &BinOp, e1: Self::V, e2: Self::V, k: &K) -> AbstractResult<'a, V>
+        where K: ?Sized + Fn(&mut Self, Self::V) -> AbstractResult<'a, V>
+    {
+        if let Some(err) = V::op_alarm(o, &e1, &e2) {
+            self.alarm(err);
+        }
+        if *o == BinOp::Proj {
+            self.index(&e2);
+        }
+        self.with_result(V::op(o, e1, e2), k)
+    }
+
+    fn denote_unary<K>(&mut self, o: &UnOp, e: Self::V, k: &K) -> AbstractResult<'a, V>
+        where K: ?Sized + Fn(&mut Self, Self::V) -> AbstractResult<'a, V>
+    {
+        if let Some(err) = V::unop_alarm(o, &e) {
+            self.alarm(err);
+        }
+        self.with_result(V::unop(o, e), k)
+    }
+
+    fn denote_update<K>(&mut self, agg: Self::V, i: Self::V, v: Self::V, k: &K) -> AbstractResult<'a, V>
+        where K: ?Sized + Fn(&mut Self, Self::V) -> AbstractResult<'a, V>
+    {
+        if let Some(err) = V::op_alarm(&BinOp::Proj, &agg, &i) {
+            self.alarm(err);
+        }
+        self.index(&i);
+        self.with_result(V::update(agg, i, v), k)
+    }
+
+    fn denote_push<K>(&mut self, agg: Self::V, v: Self::V, k: &K) -> AbstractResult<'a, V>
+        where K: ?Sized + Fn(&mut Self, Self::V) -> AbstractResult<'a, V>
+    {
+        self.with_result(V::push(agg, v), k)
+    }
+
+    fn inj_val(&self, l: &Lit) -> V {
+        V::abs_lit(l)
+    }
+
+    fn inj_loc(&self, l: AbsAddr) -> V {
+        self.escaped.borrow_mut().insert(l);
+        V::from_loc(&l)
+    }
+
+    fn inj_tuple(&self, vs: Vec<V>) -> V {
+        V::from_tuple(vs)
+    }
+
+    fn inj_vector(&self, vs: Vec<V>) -> V {
+        V::from_vector(vs)
+    }
+
+    // Follow each address `v` may hold; one that may hold any could point to
+    // any cell whose address was taken
+    fn unwrap_ptr<K>(&mut self, v: V, k: &K) -> AbstractResult<'a, V>
+        where K: ?Sized + Fn(&mut Self, Self::L) -> AbstractResult<'a, V>
+    {
+        let ls = V::addrs(&v).unwrap_or_else(|| {
+            let mut ls: Vec<_> = self.escaped.borrow().iter().copied().collect();
+            ls.sort_unstable();
+            ls
+        });
+        if ls.is_empty() && !v.is_bottom() {
+            self.alarm(RuntimeErrorKind::NotAPointer(format!("{:?}", v)));
+        }
+        self.fork(ls, k)
+    }
+
+    fn do_match<K>(&mut self, scrutinee: &Expr, p: &PatternKind, v: &Self::V, k: &K) -> AbstractResult<'a, V>
+    where
+        K: ?Sized + Fn(&mut Self, bool) -> AbstractResult<'a, V>
+    {
+        match p {
+            PatternKind::PLiteral(l) => {
+                let lit = V::abs_lit(l);
+                // Whether `v` may match, and whether it may not
+                let may_match = !V::assume(&BinOp::Eq, v, &lit, true).0.is_bottom();
+                let may_fail = !self.unmatched(scrutinee, p, v).is_bottom();
+                let outcomes = [true, false].iter().copied().filter(|b| if *b { may_match } else { may_fail }).collect();
+                self.fork(outcomes, &|e: &mut Self, b: bool| {
+                    // `if` matches its guard against `true`, then `false`
+                    let feasible = match l.lit {
+                        LiteralKind::LBool(t) if is_boolean(scrutinee) => e.refine(scrutinee, b == t),
+                        _ => match e.operand(scrutinee) {
+                            Some(a) => {
+                                let (a, _) = V::assume(&BinOp::Eq, &a, &lit, b);
+                                let feasible = !a.is_bottom();
+                                e.assign(scrutinee, a);
+                                feasible
+                            }
+                            None => true,
+                        },
+                    };
+                    if feasible { k(e, b) } else { Bounce::Done(()) }
+                })
+            }
+            PatternKind::PWild => k(self, true),
+            PatternKind::PSymbol(x) => {
+                let l = self.alloc_site();
+                self.write(l, v.clone());
+                self.bind(x, l);
+                k(self, true)
+            }
+        }
+    }
+
+    // Other values than true and false fall through both arms of an `if`
+    fn unmatched(&self, scrutinee: &Expr, p: &PatternKind, v: &Self::V) -> Self::V {
+        match p {
+            PatternKind::PLiteral(Lit { lit: LiteralKind::LBool(t), loc }) if is_boolean(scrutinee) => {
+                v.meet(&V::abs_lit(&Lit { lit: LiteralKind::LBool(!t), loc: *loc }))
+            }
+            PatternKind::PLiteral(l) => V::assume(&BinOp::Eq, v, &V::abs_lit(l), false).0,
+            _ => v.clone(),
+        }
+    }
+
+    fn branch<K>(&mut self, guard: &Expr, v: &Self::V, k: &K) -> AbstractResult<'a, V>
+    where
+        K: ?Sized + Fn(&mut Self, bool) -> AbstractResult<'a, V>
+    {
+        self.fork(Self::outcomes(v), &|e: &mut Self, b: bool| {
+            if e.refine(guard, b) { k(e, b) } else { Bounce::Done(()) }
+        })
+    }
+
+    fn loop_head<K>(&mut self, s: &Statement, k: &K) -> AbstractResult<'a, V>
+    where
+        K: ?Sized + Fn(&mut Self) -> AbstractResult<'a, V>
+    {
+        let key = (s.loc, self.state.frame().context.clone());
+        if !self.enter_head(key) {
+            return Bounce::Done(());
+        }
+        if !self.narrowing {
+            self.restart_inner(s);
+        }
+        k(self)
+    }
+
+    fn fn_decl(&self, f: &Symbol) -> Option<&'a FnDecl>
+    {
+        self.decls.get(f).copied()
+    }
+
+    fn fail(&mut self, err: RuntimeErrorKind) -> AbstractResult<'a, V>
+    {
+        self.alarm(err);
+        Bounce::Done(())
+    }
+
+    fn suspend(&mut self, k: Thunk<'a, Self, AbstractResult<'a, V>>) -> AbstractResult<'a, V>
+    {
+        Bounce::More(k)
+    }
+}
+
+type AbsKont<'a, V> = Kont<'a, AbstractEvaluator<'a, V>, AbstractResult<'a, V>>;
+
+fn abstract_cb<'a, V>(eval: &mut AbstractEvaluator<'a, V>,
+                      e: &'a Expr,
+                      k: ValKont<'a, AbstractEvaluator<'a, V>, V, AbstractResult<'a, V>>) -> AbstractResult<'a, V>
+where V: AbstractValue<AbsAddr> + 'a
+{
+    eval.state.locs.push(e.loc);
+    eval_expr(eval, e, abstract_cb, abstract_stmt_cb, Rc::new(move |eval: &mut AbstractEvaluator<'a, V>, v| {
+        eval.state.locs.pop();
+        k(eval, v)
+    }))
+}
+
+// Whether `e` can only evaluate to true or false
+fn is_boolean(e: &Expr) -> bool {
+    match &e.expr {
+        ExprKind::Binary(o, _, _) => matches!(o, BinOp::Lt | BinOp::Gt | BinOp::Lte | BinOp::Gte
+                                                 | BinOp::Eq | BinOp::Neq | BinOp::And | BinOp::Or),
+        ExprKind::Unary(UnOp::Negate, _) => true,
+        ExprKind::Lit(Lit { lit: LiteralKind::LBool(_), .. }) => true,
+        _ => false,
+    }
+}
+
+// Record the state on entry to `s`. The bodies of recursive calls are run
+// from the widened join of their entry states, which reaches a fixpoint, but
+// only to find what may go wrong in them: their callers go on as if the call
+// could have changed anything it can reach.
+pub fn abstract_stmt_cb<'a, V>(eval: &mut AbstractEvaluator<'a, V>,
+                               s: &'a Statement,
+                               k: AbsKont<'a, V>,
+                               exit: AbsKont<'a, V>) -> AbstractResult<'a, V>
+where V: AbstractValue<AbsAddr> + 'a
+{
+    if eval.state.frame().recursive {
+        return eval.fork(vec![true, false], &|eval: &mut AbstractEvaluator<'a, V>, run: bool| {
+            if !run {
+                eval.havoc();
+                return k(eval);
+            }
+            eval.state.frame_mut().recursive = false;
+            if !eval.enter_head((s.loc, eval.state.frame().context.clone())) {
+                return Bounce::Done(());
+            }
+            let done: AbsKont<'a, V> = Rc::new(|_: &mut AbstractEvaluator<'a, V>| Bounce::Done(()));
+            abstract_stmt_cb(eval, s, done.clone(), done)
+        });
+    }
+    let key = (s.loc, eval.state.frame().context.clone());
+    if !eval.record(key, false) {
+        return Bounce::Done(());
+    }
+    let frame = eval.state.frame_mut();
+    frame.stmt = s.loc;
+    frame.allocs = 0;
+    eval.state.locs.push(s.loc);
+    run_stmt(eval, s, abstract_cb, abstract_stmt_cb,
+             Rc::new(move |eval: &mut AbstractEvaluator<'a, V>| { eval.state.locs.pop(); k(eval) }),
+             Rc::new(move |eval: &mut AbstractEvaluator<'a, V>| { eval.state.locs.pop(); exit(eval) }))
+}
+
+impl<'a, V: AbstractValue<AbsAddr> + 'a> AbstractEvaluator<'a, V> {
+    // Analyze `m` from the start, whose `main` is `f`, keeping what is known
+    // about allocation sites and loop heads from earlier passes
+    fn run_main(&mut self, m: &'a Module, f: &'a FnDecl) {
+        let frame = Frame {
+            f: f.name.clone(),
+            context: Vec::new(),
+            store: HashMap::new(),
+            scopes: frame_scopes(&f.body),
+            ret: 0,
+            stmt: f.loc,
+            allocs: 0,
+            recursive: false,
+        };
+        self.state = AbsState { heap: BTreeMap::new(), globals: HashMap::new(), frames: vec![frame], locs: vec![f.body.loc] };
+        self.points.clear();
+        self.arrivals.clear();
+        self.explored.clear();
+        self.exit = None;
+        self.alarms.clear();
+        self.indices.clear();
+
+        let ret = self.alloc_site();
+        self.write(ret, V::zero());
+        self.state.frame_mut().ret = ret;
+
+        let done : AbsKont<'a, V> = Rc::new(|eval: &mut AbstractEvaluator<'a, V>| {
+            eval.exit = Some(match eval.exit.take() {
+                Some(s) => s.combine(&eval.state, V::join),
+                None => eval.state.clone(),
+            });
+            Bounce::Done(())
+        });
+        // As in `concrete::run_main`, the globals cannot see the arguments,
+        // which may be anything at all
+        init_globals(self, &m.globals, abstract_cb, Rc::new(move |eval: &mut AbstractEvaluator<'a, V>| {
+            if let Some(param) = f.params.first() {
+                let l = eval.alloc_site();
+                eval.write(l, V::any_vector());
+                eval.bind(&param.name, l);
+            }
+            abstract_stmt_cb(eval, &f.body, done.clone(), done.clone())
+        })).run(self);
+    }
+}
+
+// What holds on every run of a module, whatever its arguments
+#[derive(Debug, Clone)]
+pub struct Analysis<V> {
+    // The variables in scope on entry to each statement that may run, over
+    // all calling contexts
+    pub points: BTreeMap<Loc, BTreeMap<String, V>>,
+    // The variables of `main` when it returns, if it may
+    pub exit: Option<BTreeMap<String, V>>,
+    // Errors some run may stop with, in source order
+    pub alarms: Vec<(Loc, RuntimeErrorKind)>,
+    // The indices each projection or update may use, e.g. to check the
+    // stride of a loop over a vector
+    pub indices: BTreeMap<Loc, V>,
+}
+
+const NARROWING_PASSES: usize = 5;
+
+pub fn analyze<'a, V>(m: &'a Module) -> Result<Analysis<V>, RuntimeError>
+where V: AbstractValue<AbsAddr> + 'a
+{
+    let mut decls = HashMap::new();
+    let mut main = None;
+    for d in &m.functions {
+        if d.name.name == "main" {
+            main = Some(d);
+        } else {
+            decls.insert(d.name.clone(), d);
+        }
+    }
+    let f = main.ok_or(RuntimeError { error: RuntimeErrorKind::NoMain, loc: m.loc, stack: Vec::new() })?;
+
+    let mut eval = AbstractEvaluator {
+        state: AbsState { heap: BTreeMap::new(), globals: HashMap::new(), frames: Vec::new(), locs: Vec::new() },
+        decls,
+        sites: HashMap::new(),
+        summaries: HashSet::new(),
+        escaped: RefCell::new(HashSet::new()),
+        points: HashMap::new(),
+        heads: HashMap::new(),
+        narrowing: false,
+        arrivals: HashMap::new(),
+        explored: HashSet::new(),
+        exit: None,
+        alarms: Vec::new(),
+        indices: BTreeMap::new(),
+    };
+    eval.run_main(m, f);
+    // Narrowing brings back in bounds that widening gave up on. The results
+    // of every pass hold, so stop after a few even if there is more to gain.
+    eval.narrowing = true;
+    for _ in 0..NARROWING_PASSES {
+        eval.run_main(m, f);
+        let narrowed: HashMap<_, _> = eval.heads.iter().filter_map(|(key, head)| {
+            eval.arrivals.get(key).map(|a| (key.clone(), head.combine(a, V::narrow)))
+        }).collect();
+        if narrowed == eval.heads {
+            break;
+        }
+        eval.heads = narrowed;
+    }
+
+    let mut points: BTreeMap<Loc, BTreeMap<String, V>> = BTreeMap::new();
+    for ((loc, _), state) in &eval.points {
+        let vars = points.entry(*loc).or_default();
+        for (x, v) in state.vars() {
+            let v = vars.get(&x).map_or(v.clone(), |w| w.join(&v));
+            vars.insert(x, v);
+        }
+    }
+    let mut alarms = eval.alarms;
+    alarms.sort_by_key(|(loc, _)| *loc);
+    Ok(Analysis { points, exit: eval.exit.map(|s| s.vars()), alarms, indices: eval.indices })
+}
+
+impl<V: fmt::Display> Analysis<V> {
+    pub fn report(&self, file: &SourceFile) -> String {
+        let show = |vars: &BTreeMap<String, V>| {
+            vars.iter().map(|(x, v)| format!("{} = {}", x, v)).collect::<Vec<_>>().join(", ")
+        };
+        let mut out = String::new();
+        for (loc, vars) in &self.points {
+            out.push_str(&format!("{}: {}\n", file.location(*loc), file.first_line(*loc)));
+            if !vars.is_empty() {
+                out.push_str(&format!("    {}\n", show(vars)));
+            }
+        }
+        match &self.exit {
+            Some(vars) => out.push_str(&format!("On return from main:\n    {}\n", show(vars))),
+            None => out.push_str("main never returns\n"),
+        }
+        for (loc, i) in &self.indices {
+            out.push_str(&format!("{}: {} indexed by {}\n", file.location(*loc), file.first_line(*loc), i));
+        }
+        for (loc, err) in &self.alarms {
+            out.push_str(&format!("warning: may fail with {}\n{}\n", err, file.annotate(*loc)));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::analysis::*;
+    use crate::language::plaia::interval::*;
+    use crate::language::plaia::signed::*;
+    use plaia_language::language::plaia::parse;
+
+    fn signs(src: &str) -> Analysis<SignedValue> {
+        analyze(&parse::parse_module(src).unwrap()).unwrap()
+    }
+
+    // The value of `x` on entry to the statement starting with `at`
+    fn value_at<V: Clone>(a: &Analysis<V>, src: &str, at: &str, x: &str) -> V {
+        let lo = src.find(at).unwrap();
+        let (_, vars) = a.points.iter().find(|(loc, _)| loc.0 == lo).expect("statement never runs");
+        vars[x].clone()
+    }
+
+    #[test]
+    fn test_branches() {
+        let src = "def main(args : vec<i64>) = {
+                     let x : i64 = 5;
+                     let y : i64 = 0 - x;
+                     let z : i64 = x * y;
+                     if z < 0 then z = 0 - z; else z = 1;
+                     let w : i64 = args[0];
+                     if w != 0 then { let v : i64 = w; } else w = 0;
+                     if w * w < 0 then w = 1; else w = 2;
+                     let u : i64 = w;
+                   }";
+        let a = signs(src);
+        assert_eq!(value_at(&a, src, "let z", "y"), SignedValue::Num(Sign::Neg));
+        assert_eq!(value_at(&a, src, "z = 0 - z", "z"), SignedValue::Num(Sign::Neg));
+        assert_eq!(value_at(&a, src, "let w", "z"), SignedValue::Num(Sign::Pos));
+        assert_eq!(value_at(&a, src, "let v", "w"), SignedValue::Num(Sign::NonZero));
+        // Every path goes through one of the branches
+        assert_eq!(value_at(&a, src, "let u", "w"), SignedValue::Num(Sign::Pos));
+        // `z = 1` never runs
+        assert!(!a.points.keys().any(|loc| &src[loc.0..loc.1] == "z = 1;"));
+        // There may be no arguments, and signs say nothing about magnitudes
+        let alarms: Vec<_> = a.alarms.iter().map(|(loc, _)| &src[loc.0..loc.1]).collect();
+        assert_eq!(alarms, ["x * y", "0 - z", "args[0]", "w * w"]);
+    }
+
+    #[test]
+    fn test_loops() {
+        let src = "def main(args : vec<i64>) = {
+                     let i : i64 = 0;
+                     while i < 10 do i = i + 1;
+                     let j : i64 = 0 - i;
+                   }";
+        let a = signs(src);
+        assert_eq!(value_at(&a, src, "i = i + 1", "i"), SignedValue::Num(Sign::NonNeg));
+        assert_eq!(value_at(&a, src, "let j", "i"), SignedValue::Num(Sign::Pos));
+        assert_eq!(a.exit.unwrap()["j"], SignedValue::Num(Sign::Neg));
+    }
+
+    #[test]
+    fn test_calls() {
+        let src = "g : i64 = 1
+                   def abs(x : i64) = { if x < 0 then return 0 - x; return x; }
+                   def fact(n : i64) = { if n <= 0 then return 1; return n * fact(n - 1); }
+                   def main(args : vec<i64>) = {
+                     let a : i64 = abs(0 - 3);
+                     let b : i64 = abs(args[0]);
+                     let p : i64* = &a;
+                     *p = 0 - g;
+                     let c : i64 = fact(3);
+                     let t : (i64, i64) = (1, 2);
+                     let z : i64 = t[g];
+                     t[g] = 3;
+                     let w : i64 = 7;
+                   }";
+        let a = signs(src);
+        assert_eq!(value_at(&a, src, "let p", "a"), SignedValue::Num(Sign::Pos));
+        assert_eq!(value_at(&a, src, "let p", "b"), SignedValue::Num(Sign::NonNeg));
+        assert_eq!(value_at(&a, src, "let c", "a"), SignedValue::Num(Sign::Neg));
+        // The recursive call to `fact` may change `a` through `p`, and `g`
+        let exit = a.exit.unwrap();
+        assert_eq!(exit["a"], SignedValue::Top);
+        assert_eq!(exit["g"], SignedValue::Top);
+        assert_eq!(exit["c"], SignedValue::Num(Sign::Top));
+        // ... so indexing with `g` may fail, but need not
+        assert_eq!(exit["z"], SignedValue::Num(Sign::Pos));
+        assert_eq!(exit["w"], SignedValue::Num(Sign::Pos));
+        let alarms: Vec<_> = a.alarms.iter().map(|(loc, _)| &src[loc.0..loc.1]).collect();
+        assert_eq!(alarms, ["0 - x", "n * fact(n - 1)", "args[0]", "t[g]", "t[g] = 3;"]);
+    }
+
+    #[test]
+    fn test_alarms() {
+        let src = "u : vec<i64> = [1]
+                   def down(n : i64) = { if n <= 0 then return 0; return down(n - 1); }
+                   def main(args : vec<i64>) = {
+                     let t : (i64, i64) = (1, 2);
+                     let n : i64 = len(args);
+                     let d : i64 = 10 / n;
+                     let e : i64 = 10 / t[0];
+                     let f : i64 = t[n];
+                     let r : i64 = down(n);
+                     let x : i64 = u[0];
+                     let m : i64 = -9223372036854775808;
+                     let p : i64 = -m;
+                   }";
+        let a = signs(src);
+        let alarms: Vec<_> = a.alarms.iter().map(|(loc, err)| (&src[loc.0..loc.1], err)).collect();
+        assert_eq!(alarms.len(), 4, "{:?}", alarms);
+        assert_eq!(alarms[0], ("10 / n", &RuntimeErrorKind::DivisionByZero));
+        assert!(matches!(alarms[1], ("t[n]", RuntimeErrorKind::IndexMayBeOutOfBounds { .. })));
+        // The recursive call may have changed `u` to anything
+        assert!(matches!(alarms[2], ("u[0]", RuntimeErrorKind::IndexMayBeOutOfBounds { .. })));
+        assert_eq!(alarms[3], ("-m", &RuntimeErrorKind::Overflow));
+    }
+
+    #[test]
+    fn test_fall_through() {
+        // `x` is neither true nor false, so neither branch runs
+        let src = "def main(args : vec<i64>) = {
+                     let x : i64 = 2;
+                     let y : i64 = 0;
+                     if x then y = 1; else y = 2;
+                     let z : i64 = y;
+                   }";
+        let a: Analysis<IntervalValue> = analyze(&parse::parse_module(src).unwrap()).unwrap();
+        assert!(!a.points.keys().any(|loc| &src[loc.0..loc.1] == "y = 1;" || &src[loc.0..loc.1] == "y = 2;"));
+        assert_eq!(a.exit.unwrap()["z"].to_string(), "0");
+        assert!(a.alarms.is_empty());
+    }
+
+    #[test]
+    fn test_many_branches() {
+        let body: String = (0..2000).map(|i| format!("if x > {} then y = 1; else y = 2;\n", i)).collect();
+        let src = format!("def main(args : vec<i64>) = {{
+                             let x : i64 = args[0];
+                             let y : i64 = 0;
+                             {}
+                             let z : i64 = y;
+                           }}", body);
+        let a = signs(&src);
+        assert_eq!(value_at(&a, &src, "let z", "y"), SignedValue::Num(Sign::Pos));
+    }
+
+    #[test]
+    fn test_recursion() {
+        // Only the call `f(0)`, three calls deep, divides by zero
+        let src = "def f(n : i64) = {
+                     let d : i64 = 10 / n;
+                     if n > 0 then return f(n - 1);
+                     return 0;
+                   }
+                   def main(args : vec<i64>) = { let r : i64 = f(3); }";
+        let m = parse::parse_module(src).unwrap();
+        let division = [("10 / n", RuntimeErrorKind::DivisionByZero)];
+        let alarms = |alarms: &[(Loc, RuntimeErrorKind)]| -> Vec<_> {
+            alarms.iter().map(|(loc, err)| (&src[loc.0..loc.1], err.clone())).collect()
+        };
+        assert_eq!(alarms(&signs(src).alarms), division);
+        let a: Analysis<IntervalValue> = analyze(&m).unwrap();
+        assert_eq!(alarms(&a.alarms), division);
+    }
+
+    #[test]
+    fn test_narrowing() {
+        let src = "def main(args : vec<i64>) = {
+                     let i : i64 = 0;
+                     let v : vec<i64> = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+                     while i < 10 do { v[i] = i; i = i + 1; }
+                     let n : i64 = args[0];
+                     let m : i64 = n + 1;
+                     if n < 0 then m = 1; else m = 2;
+                     let k : i64 = m;
+                   }";
+        let a: Analysis<IntervalValue> = analyze(&parse::parse_module(src).unwrap()).unwrap();
+        // Widening alone would leave `i` unbounded, and `i + 1` may overflow
+        assert_eq!(value_at(&a, src, "v[i] = i;", "i").to_string(), "[0, 9]");
+        assert_eq!(value_at(&a, src, "let n", "i").to_string(), "10");
+        assert_eq!(value_at(&a, src, "let k", "m").to_string(), "[1, 2]");
+        let alarms: Vec<_> = a.alarms.iter().map(|(loc, err)| (&src[loc.0..loc.1], err)).collect();
+        let any = RuntimeErrorKind::IndexMayBeOutOfBounds { index: "0".into(), len: "[0, +∞]".into() };
+        assert_eq!(alarms, [("args[0]", &any), ("n + 1", &RuntimeErrorKind::Overflow)]);
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        let src = "def main(args : vec<i64>) = {
+                     let i : i64 = 0;
+                     while i < 10 do {
+                       let j : i64 = 0;
+                       while j < i do j = j + 1;
+                       i = i + 1;
+                     }
+                     let k : i64 = i;
+                   }";
+        let a: Analysis<IntervalValue> = analyze(&parse::parse_module(src).unwrap()).unwrap();
+        // The inner loop starts over on each iteration of the outer one, so
+        // widening there leaves `i` alone
+        assert_eq!(value_at(&a, src, "j = j + 1;", "i").to_string(), "[1, 9]");
+        assert_eq!(value_at(&a, src, "i = i + 1;", "i").to_string(), "[0, 9]");
+        assert_eq!(value_at(&a, src, "let k", "i").to_string(), "10");
+        assert!(a.alarms.is_empty());
+    }
+}
@@ -1,11 +1,18 @@
 use std::collections::HashMap;
+use crate::language::plaia::error::*;
 use crate::language::plaia::interpret::*;
+use crate::language::plaia::profile::Profile;
+use crate::language::plaia::trace::*;
 use plaia_language::language::plaia::ast::*;
 use plaia_language::language::plaia::ast::{Statement, FnDecl};
+use plaia_language::language::plaia::source::SourceFile;
+use std::cell::RefCell;
+use std::io::Write as _;
+use std::rc::Rc;
 // A simple concrete implementation:
 pub type SimpleAddr = usize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SimpleValue {
     VInt(i64),
     VAddr(SimpleAddr),
@@ -13,25 +20,71 @@ pub enum SimpleValue {
     VVector(Vec<SimpleValue>),
 }
 
-type SimpleStore = HashMap<Symbol, SimpleAddr>;
+pub type SimpleStore = HashMap<Symbol, SimpleAddr>;
 
-#[derive(Debug, Clone)]
-pub struct ConcreteEvaluator<Val> {
+// The bindings that the declarations in a scope replaced (`None` if the name
+// was not bound), to put back when the scope is exited
+pub type Shadowed = Vec<(Symbol, Option<SimpleAddr>)>;
+
+// A shared handle to something watching a run of a `ConcreteEvaluator`
+pub type Observer<'a, Val> = Rc<RefCell<dyn ExecutionObserver<Val, SimpleAddr> + 'a>>;
+
+#[derive(Clone)]
+pub struct ConcreteEvaluator<'a, Val> {
     heap: Vec<Val>,
+    globals: SimpleStore,
     frames: Vec<SimpleStore>,
+    // The open scopes of each frame, innermost last. The outermost block of
+    // a function body is the scope of the frame itself, so its bindings are
+    // dropped with the frame rather than restored.
+    scopes: Vec<Vec<Shadowed>>,
+    returns: Vec<SimpleAddr>,
     cur_frame: usize,
-    trace: Vec<(Vec<Val>, SimpleStore, Loc)>,
-    decls: HashMap<Symbol, FnDecl>,
+    // Innermost last: the expressions/statements being evaluated and the
+    // active calls, for error reporting
+    locs: Vec<Loc>,
+    calls: Vec<(Symbol, Loc)>,
+    decls: HashMap<Symbol, &'a FnDecl>,
+    // Statements executed so far, the last one, and how many more may run
+    // (if limited)
+    steps: u64,
+    last_stmt: Option<Loc>,
+    fuel: Option<u64>,
+    observers: Vec<Observer<'a, Val>>,
 }
-type SimpleEvaluator = ConcreteEvaluator<SimpleValue>;
+type SimpleEvaluator<'a> = ConcreteEvaluator<'a, SimpleValue>;
+
+// Running a program to completion drives the trampoline until it is `Done`
+pub type ConcreteResult<'a> = Bounce<'a, SimpleEvaluator<'a>, Result<(), RuntimeError>>;
 
 pub trait ValCompute<L> : std::fmt::Debug + Clone {
     fn zero() -> Self;
     fn is_true(v: &Self) -> bool;
-    fn op(o: &BinOp, e1: Self, e2: Self) -> Self;
+    fn op(o: &BinOp, e1: Self, e2: Self) -> Result<Self, RuntimeErrorKind>;
+    fn unop(o: &UnOp, e: Self) -> Result<Self, RuntimeErrorKind>;
+    fn update(agg: Self, i: Self, v: Self) -> Result<Self, RuntimeErrorKind>;
+    fn push(agg: Self, v: Self) -> Result<Self, RuntimeErrorKind>;
     fn from_lit(l: &Lit) -> Self;
     fn from_loc(l: &L) -> Self;
-    fn unwrap_loc(v: Self) -> L;
+    fn from_tuple(vs: Vec<Self>) -> Self;
+    fn from_vector(vs: Vec<Self>) -> Self;
+    fn unwrap_loc(v: Self) -> Result<L, RuntimeErrorKind>;
+}
+
+fn index(len: usize, i: i64) -> Result<usize, RuntimeErrorKind> {
+    if i >= 0 && (i as usize) < len {
+        Ok(i as usize)
+    } else {
+        Err(RuntimeErrorKind::IndexOutOfBounds { index: i, len })
+    }
+}
+
+fn arith(r: Option<i64>) -> Result<SimpleValue, RuntimeErrorKind> {
+    r.map(SimpleValue::VInt).ok_or(RuntimeErrorKind::Overflow)
+}
+
+fn truth(b: bool) -> Result<SimpleValue, RuntimeErrorKind> {
+    Ok(SimpleValue::VInt(if b { 1 } else { 0 }))
 }
 
 impl ValCompute<SimpleAddr> for SimpleValue {
@@ -47,38 +100,77 @@ impl ValCompute<SimpleAddr> for SimpleValue {
             false
         }
     }
-    fn op(o: &BinOp, e1: Self, e2: Self) -> Self
+    fn op(o: &BinOp, e1: Self, e2: Self) -> Result<Self, RuntimeErrorKind>
     {
-        match (o, e1, e2) {
-            (BinOp::Proj, SimpleValue::VTuple(vs), SimpleValue::VInt(v2)) =>
-                vs[v2 as usize].clone(),
+        match (o, &e1, &e2) {
+            (BinOp::Proj, SimpleValue::VTuple(vs), SimpleValue::VInt(v2)) |
             (BinOp::Proj, SimpleValue::VVector(vs), SimpleValue::VInt(v2)) =>
-                vs[v2 as usize].clone(),
+                Ok(vs[index(vs.len(), *v2)?].clone()),
             (BinOp::Add, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(v1 + v2),
+                arith(v1.checked_add(*v2)),
             (BinOp::Sub, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(v1 - v2),
+                arith(v1.checked_sub(*v2)),
             (BinOp::Mul, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(v1 * v2),
+                arith(v1.checked_mul(*v2)),
+            (BinOp::Div, SimpleValue::VInt(_), SimpleValue::VInt(0)) =>
+                Err(RuntimeErrorKind::DivisionByZero),
             (BinOp::Div, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(v1 / v2),
+                arith(v1.checked_div(*v2)),
             (BinOp::Eq, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(if v1 == v2 { 1 } else { 0 }),
+                truth(v1 == v2),
             (BinOp::Neq, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(if v1 != v2 { 1 } else { 0 }),
+                truth(v1 != v2),
             (BinOp::Lt, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(if v1 <  v2 { 1 } else { 0 }),
+                truth(v1 <  v2),
             (BinOp::Gt, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(if v1 >  v2 { 1 } else { 0 }),
+                truth(v1 >  v2),
             (BinOp::Lte, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(if v1 <= v2 { 1 } else { 0 }),
+                truth(v1 <= v2),
             (BinOp::Gte, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(if v1 >= v2 { 1 } else { 0 }),
+                truth(v1 >= v2),
             (BinOp::And, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(if v1 == 1 && v2 == 1 { 1 } else { 0 }),
+                truth(*v1 == 1 && *v2 == 1),
             (BinOp::Or, SimpleValue::VInt(v1), SimpleValue::VInt(v2)) =>
-                SimpleValue::VInt(if v1 == 1 || v2 == 1 { 1 } else { 0 }),
-            _ => panic!("Type error")
+                truth(*v1 == 1 || *v2 == 1),
+            _ => Err(RuntimeErrorKind::TypeError(format!("cannot apply {:?} to {:?} and {:?}", o, e1, e2)))
+        }
+    }
+    fn unop(o: &UnOp, e: Self) -> Result<Self, RuntimeErrorKind>
+    {
+        match (o, &e) {
+            (UnOp::Negate, SimpleValue::VInt(v)) =>
+                truth(*v != 1),
+            (UnOp::Minus, SimpleValue::VInt(v)) =>
+                arith(v.checked_neg()),
+            (UnOp::Len, SimpleValue::VVector(vs)) |
+            (UnOp::Len, SimpleValue::VTuple(vs)) =>
+                Ok(SimpleValue::VInt(vs.len() as i64)),
+            _ => Err(RuntimeErrorKind::TypeError(format!("cannot apply {:?} to {:?}", o, e)))
+        }
+    }
+    fn update(agg: Self, i: Self, v: Self) -> Result<Self, RuntimeErrorKind>
+    {
+        match (agg, i) {
+            (SimpleValue::VTuple(mut vs), SimpleValue::VInt(i)) => {
+                let i = index(vs.len(), i)?;
+                vs[i] = v;
+                Ok(SimpleValue::VTuple(vs))
+            }
+            (SimpleValue::VVector(mut vs), SimpleValue::VInt(i)) => {
+                let i = index(vs.len(), i)?;
+                vs[i] = v;
+                Ok(SimpleValue::VVector(vs))
+            }
+            (agg, i) => Err(RuntimeErrorKind::TypeError(format!("cannot index {:?} with {:?}", agg, i)))
+        }
+    }
+    fn push(agg: Self, v: Self) -> Result<Self, RuntimeErrorKind>
+    {
+        if let SimpleValue::VVector(mut vs) = agg {
+            vs.push(v);
+            Ok(SimpleValue::VVector(vs))
+        } else {
+            Err(RuntimeErrorKind::TypeError(format!("cannot push onto {:?}", agg)))
         }
     }
     fn from_lit(l: &Lit) -> Self
@@ -94,87 +186,266 @@ impl ValCompute<SimpleAddr> for SimpleValue {
         SimpleValue::VAddr(*l)
     }
 
-    fn unwrap_loc(v: Self) -> SimpleAddr
+    fn from_tuple(vs: Vec<Self>) -> Self
+    {
+        SimpleValue::VTuple(vs)
+    }
+
+    fn from_vector(vs: Vec<Self>) -> Self
+    {
+        SimpleValue::VVector(vs)
+    }
+
+    fn unwrap_loc(v: Self) -> Result<SimpleAddr, RuntimeErrorKind>
     {
         if let SimpleValue::VAddr(addr) = v {
-            addr
+            Ok(addr)
         } else {
-            panic!("Not an address!")
+            Err(RuntimeErrorKind::NotAPointer(format!("{:?}", v)))
+        }
+    }
+}
+
+impl ToJson for SimpleValue {
+    fn to_json(&self) -> String {
+        let list = |vs: &[SimpleValue]| vs.iter().map(|v| v.to_json()).collect::<Vec<_>>().join(",");
+        match self {
+            SimpleValue::VInt(n) => n.to_string(),
+            SimpleValue::VAddr(l) => format!("{{\"addr\":{}}}", l),
+            SimpleValue::VTuple(vs) => format!("{{\"tuple\":[{}]}}", list(vs)),
+            SimpleValue::VVector(vs) => format!("[{}]", list(vs)),
+        }
+    }
+}
+
+impl <'a, Val: Clone> ConcreteEvaluator<'a, Val> {
+    // Stop with `OutOfFuel` once `fuel` statements have been executed
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    // The caller keeps its own handle to `o` to get at the results
+    pub fn add_observer(&mut self, o: Observer<'a, Val>) {
+        self.observers.push(o);
+    }
+
+    pub fn state(&self) -> State<Val> {
+        State { heap: self.heap.clone(), globals: self.globals.clone(), frames: self.frames.clone() }
+    }
+
+    fn notify<F>(&self, mut f: F)
+    where
+        F: FnMut(&mut dyn ExecutionObserver<Val, SimpleAddr>)
+    {
+        for o in &self.observers {
+            f(&mut *o.borrow_mut());
+        }
+    }
+
+    // All changes to the heap and the frames go through these, so that
+    // observers hear about them
+    fn push_heap(&mut self, v: Val) -> SimpleAddr {
+        let l = self.heap.len();
+        self.notify(|o| o.alloc(&l, &v));
+        self.heap.push(v);
+        l
+    }
+
+    fn write_heap(&mut self, l: SimpleAddr, v: Val) {
+        self.notify(|o| o.heap_write(&l, &v));
+        self.heap[l] = v;
+    }
+
+    fn bind(&mut self, x: &Symbol, l: SimpleAddr) {
+        self.notify(|o| o.store_write(x, &l));
+        let old = self.frames[self.cur_frame].insert(x.clone(), l);
+        let scopes = &mut self.scopes[self.cur_frame];
+        if scopes.len() > 1 {
+            scopes.last_mut().unwrap().push((x.clone(), old));
+        }
+    }
+
+    fn bind_global(&mut self, x: &Symbol, l: SimpleAddr) {
+        self.notify(|o| o.global_write(x, &l));
+        self.globals.insert(x.clone(), l);
+    }
+
+    // Count one more statement, or say that the budget is used up
+    fn consume_fuel(&mut self) -> bool {
+        match self.fuel {
+            Some(0) => false,
+            Some(n) => {
+                self.fuel = Some(n - 1);
+                self.steps += 1;
+                true
+            }
+            None => {
+                self.steps += 1;
+                true
+            }
+        }
+    }
+
+    fn error(&self, error: RuntimeErrorKind) -> RuntimeError {
+        RuntimeError {
+            error,
+            loc: self.locs.last().copied().unwrap_or((0, 0)),
+            stack: self.calls.clone(),
         }
     }
+
+    // Continue with the result of a `ValCompute` operation, or stop
+    fn with_result<T,R,K>(&mut self, r: Result<T, RuntimeErrorKind>, k: &K) -> R
+    where
+        R: From<RuntimeError>,
+        K: ?Sized + Fn(&mut Self, T) -> R
+    {
+        match r {
+            Ok(v) => k(self, v),
+            Err(e) => R::from(self.error(e)),
+        }
+    }
+}
+
+impl <'a, E> From<RuntimeError> for Bounce<'a, E, Result<(), RuntimeError>> {
+    fn from(e: RuntimeError) -> Self {
+        Bounce::Done(Err(e))
+    }
 }
 
-impl <Val,R> Evaluator<R> for ConcreteEvaluator<Val> where Val : ValCompute<SimpleAddr> {
+impl <'a,Val,R> Evaluator<'a,R> for ConcreteEvaluator<'a,Val>
+where
+    Val : ValCompute<SimpleAddr> + 'a,
+    R : From<RuntimeError> + From<Thunk<'a, Self, R>>,
+{
     type V = Val;
     type L = SimpleAddr;
 
 
-    fn find_store<K:?Sized>(&mut self, s: &Symbol, k: &K) -> R
-    where K: Fn(&mut Self, Self::L) -> R
+    fn find_store<K>(&mut self, s: &Symbol, k: &K) -> R
+    where K: ?Sized + Fn(&mut Self, Self::L) -> R
     {
-        let l = *self.frames[self.cur_frame].get(s).unwrap();
-        k (self, l)
+        let l = self.frames[self.cur_frame].get(s)
+                    .or_else(|| self.globals.get(s))
+                    .copied()
+                    .ok_or_else(|| RuntimeErrorKind::UnboundVariable(s.clone()));
+        self.with_result(l, k)
     }
 
-    fn find_heap<K:?Sized>(&mut self, s: &Self::L, k: &K) -> R
-    where K: Fn(&mut Self, Self::V) -> R
+    fn find_heap<K>(&mut self, s: &Self::L, k: &K) -> R
+    where K: ?Sized + Fn(&mut Self, Self::V) -> R
     {
         let v = self.heap[*s].clone();
         k(self, v)
     }
 
-    fn alloc<K:?Sized>(&mut self, k: &K) -> R
-        where K: Fn(&mut Self, Self::L) -> R
+    fn alloc<K>(&mut self, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::L) -> R
     {
-        self.heap.push(Val::zero());
-        let l = self.heap.len() - 1;
+        let l = self.push_heap(Val::zero());
         k(self, l)
     }
 
-    fn update_store<K:?Sized>(&mut self, x: &Symbol, l: &Self::L, k: &K) -> R
-    where K: Fn(&mut Self) -> R
+    fn update_store<K>(&mut self, x: &Symbol, l: &Self::L, k: &K) -> R
+    where K: ?Sized + Fn(&mut Self) -> R
     {
-        self.frames[self.cur_frame].insert(x.clone(), *l);
+        self.bind(x, *l);
         k(self)
     }
 
-    fn update_heap<K:?Sized>(&mut self, l: &Self::L, v: Self::V, k: &K) -> R
-    where K: Fn(&mut Self) -> R
+    fn update_global<K>(&mut self, x: &Symbol, l: &Self::L, k: &K) -> R
+    where K: ?Sized + Fn(&mut Self) -> R
     {
-        self.heap[*l] = v;
+        self.bind_global(x, *l);
         k(self)
     }
 
-    fn push_frame<K:?Sized>(&mut self, frame: Vec<(Symbol, Self::V)>, k: &K) -> R
-    where K: Fn(&mut Self) -> R
+    fn update_heap<K>(&mut self, l: &Self::L, v: Self::V, k: &K) -> R
+    where K: ?Sized + Fn(&mut Self) -> R
     {
-        let mut map = HashMap::new();
-        for (k,v) in frame {
-            self.heap.push(v);
-            map.insert(k,self.heap.len()-1);
-        }
+        self.write_heap(*l, v);
+        k(self)
+    }
 
-        self.frames.push(map);
+    fn push_frame<K>(&mut self, f: &Symbol, frame: Vec<(Symbol, Self::V)>, k: &K) -> R
+    where K: ?Sized + Fn(&mut Self) -> R
+    {
+        let call_loc = self.locs.last().copied().unwrap_or((0, 0));
+        self.calls.push((f.clone(), call_loc));
+
+        self.frames.push(HashMap::new());
+        self.scopes.push(frame_scopes(&self.decls[f].body));
         self.cur_frame += 1;
+        for (x,v) in frame {
+            let l = self.push_heap(v);
+            self.bind(&x, l);
+        }
+
+        let ret = self.push_heap(Val::zero());
+        self.returns.push(ret);
         k(self)
     }
 
+    fn enter_scope(&mut self)
+    {
+        self.scopes[self.cur_frame].push(Vec::new());
+    }
+
+    fn exit_scope(&mut self)
+    {
+        let shadowed = self.scopes[self.cur_frame].pop().expect("no open scope");
+        // Undo the bindings newest first, in case a name was bound twice
+        for (x, old) in shadowed.into_iter().rev() {
+            match old {
+                Some(l) => {
+                    self.notify(|o| o.store_write(&x, &l));
+                    self.frames[self.cur_frame].insert(x, l);
+                }
+                None => {
+                    self.notify(|o| o.store_remove(&x));
+                    self.frames[self.cur_frame].remove(&x);
+                }
+            }
+        }
+    }
+
     fn pop_frame(&mut self)
     {
         self.frames.pop();
+        self.scopes.pop();
+        self.returns.pop();
+        self.calls.pop();
         self.cur_frame -= 1;
     }
 
     fn return_loc(&mut self) -> Self::L
     {
-        0
+        self.returns[self.cur_frame]
     }
 
     // "Meaning"
-    fn denote<K:?Sized>(&mut self, o: &BinOp, e1: Self::V, e2: Self::V, k: &K) -> R
-        where K: Fn(&mut Self, Self::V) -> R
+    fn denote<K>(&mut self, o: &BinOp, e1: Self::V, e2: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R
     {
-        k(self, Val::op(o, e1, e2))
+        self.with_result(Val::op(o, e1, e2), k)
+    }
+
+    fn denote_unary<K>(&mut self, o: &UnOp, e: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R
+    {
+        self.with_result(Val::unop(o, e), k)
+    }
+
+    fn denote_update<K>(&mut self, agg: Self::V, i: Self::V, v: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R
+    {
+        self.with_result(Val::update(agg, i, v), k)
+    }
+
+    fn denote_push<K>(&mut self, agg: Self::V, v: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R
+    {
+        self.with_result(Val::push(agg, v), k)
     }
 
     fn inj_val(&self, l: &Lit) -> Val {
@@ -185,53 +456,108 @@ impl <Val,R> Evaluator<R> for ConcreteEvaluator<Val> where Val : ValCompute<Simp
         Val::from_loc(&v)
     }
 
-    fn unwrap_ptr(&self, v: Val) -> SimpleAddr {
-        Val::unwrap_loc(v)
+    fn inj_tuple(&self, vs: Vec<Val>) -> Val {
+        Val::from_tuple(vs)
+    }
+
+    fn inj_vector(&self, vs: Vec<Val>) -> Val {
+        Val::from_vector(vs)
+    }
+
+    fn unwrap_ptr<K>(&mut self, v: Val, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::L) -> R
+    {
+        self.with_result(Val::unwrap_loc(v), k)
     }
 
-    fn do_match<K:?Sized>(&mut self, p: &PatternKind, v: &Self::V, k: &K) -> R
+    fn do_match<K>(&mut self, _scrutinee: &Expr, p: &PatternKind, v: &Self::V, k: &K) -> R
     where
-        K: Fn(&mut Self, bool) -> R
+        K: ?Sized + Fn(&mut Self, bool) -> R
     {
         match p {
             PatternKind::PLiteral(l) => {
                 let lz = Self::V::from_lit(l);
-                let b  = Self::V::op(&BinOp::Eq, lz, v.clone());
-                k(self, Self::V::is_true(&b))
+                let b  = Self::V::op(&BinOp::Eq, lz, v.clone()).map(|b| Self::V::is_true(&b));
+                self.with_result(b, k)
+            }
+            PatternKind::PWild => k(self, true),
+            PatternKind::PSymbol(x) => {
+                // Bind the scrutinee to a fresh slot for the arm to use
+                let l = self.push_heap(v.clone());
+                self.bind(x, l);
+                k(self, true)
             }
-            _ => todo!()
         }
     }
 
-    fn fn_decl(&self, f: &Symbol) -> FnDecl
+    fn branch<K>(&mut self, _guard: &Expr, v: &Self::V, k: &K) -> R
+    where
+        K: ?Sized + Fn(&mut Self, bool) -> R
     {
-        Clone::clone(self.decls.get(f).unwrap())
+        k(self, Self::V::is_true(v))
     }
-}
 
-pub fn concrete_stmt_cb<R>(eval: &mut SimpleEvaluator,
-                           s: &Statement,
-                           k: &(dyn Fn(&mut SimpleEvaluator) -> R)) -> R
-{
-    run_stmt(eval, s, &concrete_cb, &concrete_stmt_cb, k)
+    fn loop_head<K>(&mut self, _s: &Statement, k: &K) -> R
+    where
+        K: ?Sized + Fn(&mut Self) -> R
+    {
+        k(self)
+    }
+
+    fn fn_decl(&self, f: &Symbol) -> Option<&'a FnDecl>
+    {
+        self.decls.get(f).copied()
+    }
+
+    fn fail(&mut self, err: RuntimeErrorKind) -> R
+    {
+        R::from(self.error(err))
+    }
+
+    fn suspend(&mut self, k: Thunk<'a, Self, R>) -> R
+    {
+        R::from(k)
+    }
+
+    fn observe<F>(&mut self, f: F)
+    where
+        F: FnMut(&mut dyn ExecutionObserver<Self::V, Self::L>)
+    {
+        self.notify(f)
+    }
 }
 
-fn concrete_cb<R>(eval: &mut SimpleEvaluator,
-                  e: &Expr,
-                  k: &(dyn Fn(&mut SimpleEvaluator, SimpleValue) -> R)) -> R
+fn concrete_cb<'a, R>(eval: &mut SimpleEvaluator<'a>,
+                      e: &'a Expr,
+                      k: ValKont<'a, SimpleEvaluator<'a>, SimpleValue, R>) -> R
+where R: From<RuntimeError> + From<Thunk<'a, SimpleEvaluator<'a>, R>> + 'a
 {
-    eval_expr(eval, e, &concrete_cb, &tracing_concrete_stmt_cb, k)
+    eval.locs.push(e.loc);
+    eval_expr(eval, e, concrete_cb, concrete_stmt_cb, Rc::new(move |eval: &mut SimpleEvaluator<'a>, v| {
+        eval.locs.pop();
+        k(eval, v)
+    }))
 }
 
-fn tracing_concrete_stmt_cb<R>(eval: &mut SimpleEvaluator,
-                               s: &Statement,
-                               k: &(dyn Fn(&mut SimpleEvaluator) -> R)) -> R
+pub fn concrete_stmt_cb<'a, R>(eval: &mut SimpleEvaluator<'a>,
+                               s: &'a Statement,
+                               k: Kont<'a, SimpleEvaluator<'a>, R>,
+                               exit: Kont<'a, SimpleEvaluator<'a>, R>) -> R
+where R: From<RuntimeError> + From<Thunk<'a, SimpleEvaluator<'a>, R>> + 'a
 {
-    let h  = eval.heap.clone();
-    let st = eval.frames[eval.cur_frame].clone();
-    let p = (h, st, s.loc);
-    eval.trace.push(p);
-    run_stmt(eval, s, &concrete_cb, &tracing_concrete_stmt_cb, k)
+    if !eval.consume_fuel() {
+        // Report the last statement that did run
+        return R::from(RuntimeError {
+            error: RuntimeErrorKind::OutOfFuel { steps: eval.steps },
+            loc: eval.last_stmt.unwrap_or(s.loc),
+            stack: eval.calls.clone(),
+        });
+    }
+    eval.last_stmt = Some(s.loc);
+    eval.locs.push(s.loc);
+    run_stmt(eval, s, concrete_cb, concrete_stmt_cb,
+             Rc::new(move |eval: &mut SimpleEvaluator<'a>| { eval.locs.pop(); k(eval) }),
+             Rc::new(move |eval: &mut SimpleEvaluator<'a>| { eval.locs.pop(); exit(eval) }))
 }
 
 
@@ -249,66 +575,497 @@ fn tracing_concrete_stmt_cb<R>(eval: &mut SimpleEvaluator,
 //     eval_expr(&mut eval, &e, &concrete_cb, &tracing_concrete_stmt_cb, ret)
 // }
 
-pub fn initial_state(m: &Module, mut args: Vec<String>) -> (ConcreteEvaluator<SimpleValue>, FnDecl)
+// The scopes a frame running `body` starts with. A block body opens the
+// frame's own scope when it starts; any other body gets one up front, so that
+// the blocks inside it are nested scopes.
+pub fn frame_scopes(body: &Statement) -> Vec<Shadowed> {
+    match body.stmt {
+        StatementKind::Block(_) => Vec::new(),
+        _ => vec![Vec::new()],
+    }
+}
+
+pub fn initial_state(m: &Module, args: Vec<String>) -> Result<(SimpleEvaluator<'_>, &FnDecl), RuntimeError>
 {
+    let fail = |error| RuntimeError { error, loc: m.loc, stack: Vec::new() };
+
     // Traverse module and look for "main"
     let mut decls  = HashMap::new();
     let mut main   = None;
     for d in &m.functions {
         if d.name.name == "main" {
-            main = Some(d.clone());
+            main = Some(d);
         } else {
-            decls.insert(d.name.clone(), d.clone());
+            decls.insert(d.name.clone(), d);
         }
     }
 
     if let Some(mainfn) = main {
         let cur_frame = 0;
-        let iargs     = SimpleValue::VVector(args.iter_mut()
-                                             .map(|s| SimpleValue::VInt(s.parse::<i64>().unwrap()))
-                                             .collect());
-        let mut frame = HashMap::new();
-        frame.insert(mainfn.params[0].name.clone(), 1);
+        let iargs     = args.iter()
+                            .map(|s| s.parse::<i64>()
+                                      .map(SimpleValue::VInt)
+                                      .map_err(|_| fail(RuntimeErrorKind::InvalidArgument(s.clone()))))
+                            .collect::<Result<_, _>>()?;
+        // Return value location, then the arguments, which `run_main` binds
+        // once the globals are initialized
+        let heap      = vec![SimpleValue::zero(), SimpleValue::VVector(iargs)];
 
-        let mut heap  = Vec::new();
-        heap.push(SimpleValue::zero()); // Return value location
-        heap.push(iargs);
-
-        let frames    = vec![frame];
-        let trace     = Vec::new();
+        let frames    = vec![HashMap::new()];
+        let returns   = vec![0];
 
         let eval = ConcreteEvaluator::<SimpleValue> {
-            trace,
             frames,
+            returns,
             heap,
+            globals: HashMap::new(),
+            scopes: vec![frame_scopes(&mainfn.body)],
             cur_frame,
+            locs: vec![mainfn.body.loc],
+            calls: Vec::new(),
             decls,
+            steps: 0,
+            last_stmt: None,
+            fuel: None,
+            observers: Vec::new(),
         };
-        (eval, mainfn)
+        Ok((eval, mainfn))
     } else {
-        panic!("No main function!")
+        Err(fail(RuntimeErrorKind::NoMain))
+    }
+}
+
+// Initialize the globals of `m`, then run the body of `f` (its `main`)
+// The globals are initialized before `main`'s arguments are bound, so they
+// cannot refer to them
+pub fn run_main<'a>(eval: &mut SimpleEvaluator<'a>, m: &'a Module, f: &'a FnDecl) -> Result<(), RuntimeError> {
+    let done : Kont<SimpleEvaluator, ConcreteResult> = Rc::new(|_e| Bounce::Done(Ok(())));
+    init_globals(eval, &m.globals, concrete_cb, Rc::new(move |eval: &mut SimpleEvaluator<'a>| {
+        let body = |eval: &mut SimpleEvaluator<'a>| {
+            run_stmt(eval, &f.body, concrete_cb, concrete_stmt_cb, done.clone(), done.clone())
+        };
+        match f.params.first() {
+            Some(param) => eval.update_store(&param.name, &1, &body),
+            None => body(eval),
+        }
+    })).run(eval)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    // One JSON object per line, see `Trace::write_json`
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunConfig {
+    pub fuel: Option<u64>,
+    pub trace: TraceFormat,
+    // Print statement and call counts, and the statements that never ran
+    pub profile: bool,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig { fuel: None, trace: TraceFormat::Text, profile: false }
     }
 }
 
+// Run `m` and return its trace, which ends with the location of `main`. An
+// error during the run is returned with the trace up to that point; only
+// errors before the program starts have no trace. `observers` watch the run
+// alongside the trace.
+pub fn record_run(m: &Module, args: Vec<String>, fuel: Option<u64>, observers: Vec<Observer<'static, SimpleValue>>) -> Result<(Trace<SimpleValue>, Option<RuntimeError>), RuntimeError> {
+    let (mut eval, f) = initial_state(m, args)?;
+    eval.set_fuel(fuel);
+    let trace = Rc::new(RefCell::new(Trace::new(eval.state())));
+    eval.add_observer(trace.clone());
+    for o in observers {
+        eval.add_observer(o);
+    }
 
-pub fn concrete_run(m: Module, args: Vec<String>, p: Option<&str>) {
-    let (mut eval, f) = initial_state(&m, args);
-    // let f        = eval.decls.get(&Symbol::new("main".to_string())).unwrap().body;
+    let result = run_main(&mut eval, m, f);
 
-    run_stmt(&mut eval, &f.body, &concrete_cb, &tracing_concrete_stmt_cb, &|_e| ());
+    drop(eval);
+    let mut trace = Rc::try_unwrap(trace).expect("trace still observed").into_inner();
+    trace.step(f.loc);
+    Ok((trace, result.err()))
+}
 
-    let h  = eval.heap.clone();
-    let st = eval.frames[eval.cur_frame].clone();
-    eval.trace.push((h, st, f.loc));
+pub fn concrete_run(m: Module, args: Vec<String>, config: &RunConfig, p: Option<&SourceFile>) -> Result<(), RuntimeError> {
+    let profile = Rc::new(RefCell::new(Profile::default()));
+    let observers: Vec<Observer<'static, SimpleValue>> = if config.profile { vec![profile.clone()] } else { Vec::new() };
+    let (trace, err) = record_run(&m, args, config.fuel, observers)?;
 
-    println!("Trace: ");
-    for (h, st, (lo, hi)) in &eval.trace {
-        for (k,l) in st {
-            let v = h.get(*l);
-            println!("\t{:?} => {:?}", k, v);
+    match config.trace {
+        TraceFormat::Text => {
+            let stdout = std::io::stdout();
+            let mut out = stdout.lock();
+            writeln!(out, "Trace: ").and_then(|_| trace.for_each_state(|step, state| {
+                for (k,l) in state.store() {
+                    let v = state.heap.get(*l);
+                    writeln!(out, "\t{:?} => {:?}", k, v)?;
+                }
+                if let Some(pp) = p {
+                    writeln!(out, "{}: {}", pp.location(step.loc), pp.snippet(step.loc))?;
+                }
+                Ok(())
+            })).expect("cannot write trace");
+        }
+        TraceFormat::Json => {
+            let stdout = std::io::stdout();
+            trace.write_json(p, &mut stdout.lock()).expect("cannot write trace");
         }
-        if let Some(pp) = p {
-            println!("{}", &pp[*lo..*hi])
+    }
+    if let (true, Some(pp)) = (config.profile, p) {
+        print!("{}", profile.borrow().report(&m, pp));
+    }
+    err.map_or(Ok(()), Err)
+}
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::concrete::*;
+    use plaia_language::language::plaia::parse;
+
+    fn try_run(src: &str) -> (SimpleEvaluator<'static>, Result<(), RuntimeError>) {
+        try_run_with(src, |_| ())
+    }
+
+    // `setup` can adjust the evaluator before the program starts
+    fn try_run_with<F>(src: &str, setup: F) -> (SimpleEvaluator<'static>, Result<(), RuntimeError>)
+    where
+        F: FnOnce(&mut SimpleEvaluator<'static>)
+    {
+        // The evaluator borrows the module, so keep it around for the test
+        let m = Box::leak(Box::new(parse::parse_module(src).unwrap()));
+        let (mut eval, f) = initial_state(m, Vec::new()).unwrap();
+        setup(&mut eval);
+        let r = run_main(&mut eval, m, f);
+        (eval, r)
+    }
+
+    fn run(src: &str) -> SimpleEvaluator<'static> {
+        let (eval, r) = try_run(src);
+        r.unwrap();
+        eval
+    }
+
+    fn lookup(eval: &SimpleEvaluator, x: &str) -> SimpleValue {
+        let l = eval.frames[eval.cur_frame][&Symbol::new(x.to_string())];
+        eval.heap[l].clone()
+    }
+
+    #[test]
+    fn test_while() {
+        let eval = run("def main(args : vec<i64>) = {
+                          let x : i64 = 0;
+                          let s : i64 = 0;
+                          while x < 4 do { s = s + x; x = x + 1; }
+                        }");
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(4));
+        assert_eq!(lookup(&eval, "s"), SimpleValue::VInt(6));
+    }
+
+    #[test]
+    fn test_if_else() {
+        let eval = run("def main(args : vec<i64>) = {
+                          let x : i64 = 3;
+                          let y : i64;
+                          if x > 5 then y = 1; else if x > 2 then y = 2; else y = 3;
+                          case y of { 1 => x = 10;, 2 => x = 20; }
+                        }");
+        assert_eq!(lookup(&eval, "y"), SimpleValue::VInt(2));
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(20));
+    }
+
+    #[test]
+    fn test_case_patterns() {
+        let eval = run("def classify(n : i64) = {
+                          case n of { 0 => return 100;, m => return m * 2; }
+                        }
+                        def main(args : vec<i64>) = {
+                          let x : i64 = classify(0) + classify(4);
+                          let y : i64;
+                          case x of { 1 => y = 1;, _ => y = 2;, 108 => y = 3; }
+                        }");
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(108));
+        assert_eq!(lookup(&eval, "y"), SimpleValue::VInt(2));
+    }
+
+    #[test]
+    fn test_tuples() {
+        let eval = run("def swap(p : (i64, bool)) = {
+                          return (p[1], p[0]);
+                        }
+                        def main(args : vec<i64>) = {
+                          let t : (bool, i64) = swap((3 + 4, true));
+                          let x : i64 = t[1];
+                        }");
+        assert_eq!(lookup(&eval, "t"),
+                   SimpleValue::VTuple(vec![SimpleValue::VInt(1), SimpleValue::VInt(7)]));
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(7));
+    }
+
+    #[test]
+    fn test_vectors() {
+        let eval = run("def main(args : vec<i64>) = {
+                          let v : vec<i64> = [1, 2];
+                          let m : vec<vec<i64>> = [v, []];
+                          let p : vec<i64>* = &v;
+                          push(v, 3);
+                          v[0] = len(v);
+                          (*p)[1] = 5;
+                          push(m[1], 7);
+                          m[0][0] = m[1][0] + len(m);
+                        }");
+        let ints = |xs: &[i64]| SimpleValue::VVector(xs.iter().map(|x| SimpleValue::VInt(*x)).collect());
+        assert_eq!(lookup(&eval, "v"), ints(&[3, 5, 3]));
+        assert_eq!(lookup(&eval, "m"), SimpleValue::VVector(vec![ints(&[9, 2]), ints(&[7])]));
+    }
+
+    #[test]
+    fn test_unary() {
+        let eval = run("def main(args : vec<i64>) = {
+                          let b : bool = !(1 > 2);
+                          let x : i64 = -(3 * 4) - -2;
+                          if !b then x = 0;
+                        }");
+        assert_eq!(lookup(&eval, "b"), SimpleValue::VInt(1));
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(-10));
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let eval = run("def main(args : vec<i64>) = {
+                          let v : vec<i64> = [4];
+                          let a : bool = len(v) > 1 && v[1] > 3;
+                          let b : bool = len(v) > 0 || v[1] > 3;
+                          let c : bool = len(v) > 0 && v[0] > 3;
+                        }");
+        assert_eq!(lookup(&eval, "a"), SimpleValue::VInt(0));
+        assert_eq!(lookup(&eval, "b"), SimpleValue::VInt(1));
+        assert_eq!(lookup(&eval, "c"), SimpleValue::VInt(1));
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let src = "def f(x : i64) = {
+                     return 10 / x;
+                   }
+                   def main(args : vec<i64>) = {
+                     let v : vec<i64> = [1];
+                     let y : i64 = f(3 - 3);
+                   }";
+        let err = try_run(src).1.unwrap_err();
+        assert_eq!(err.error, RuntimeErrorKind::DivisionByZero);
+        assert_eq!(&src[err.loc.0..err.loc.1], "10 / x");
+        assert_eq!(err.stack.len(), 1);
+        assert_eq!(&src[err.stack[0].1 .0..err.stack[0].1 .1], "f(3 - 3)");
+
+        let err = try_run(src.replace("f(3 - 3)", "v[1]").as_str()).1.unwrap_err();
+        assert_eq!(err.error, RuntimeErrorKind::IndexOutOfBounds { index: 1, len: 1 });
+        assert!(err.stack.is_empty());
+
+        let err = try_run(src.replace("f(3 - 3)", "*y").as_str()).1.unwrap_err();
+        assert_eq!(err.error, RuntimeErrorKind::UnboundVariable(Symbol::new("y".to_string())));
+
+        let err = try_run(src.replace("f(3 - 3)", "*v").as_str()).1.unwrap_err();
+        assert!(matches!(err.error, RuntimeErrorKind::NotAPointer(_)));
+
+        let err = try_run(src.replace("f(3 - 3)", "f(1, 2)").as_str()).1.unwrap_err();
+        assert!(matches!(err.error, RuntimeErrorKind::ArityMismatch { expected: 1, found: 2, .. }));
+
+        let err = try_run(src.replace("f(3 - 3)", "g(1)").as_str()).1.unwrap_err();
+        assert_eq!(err.error, RuntimeErrorKind::UnknownFunction(Symbol::new("g".to_string())));
+
+        let err = try_run(&src.replace("let y : i64 = f(3 - 3);", "f(1) = 2;")).1.unwrap_err();
+        assert_eq!(err.error, RuntimeErrorKind::NotAnLValue);
+    }
+
+    #[test]
+    fn test_return() {
+        let eval = run("def fact(n : i64) = {
+                          while n > 0 do { return n * fact(n - 1); }
+                          return 1;
+                        }
+                        def main(args : vec<i64>) = {
+                          let x : i64 = fact(5) + fact(3);
+                          return x;
+                          x = 0;
+                        }");
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(126));
+        assert_eq!(eval.heap[0], SimpleValue::VInt(126));
+    }
+
+    #[test]
+    fn test_scopes() {
+        let eval = run("def f(x : i64) = {
+                          { let x : i64 = x * 10; }
+                          return x;
+                        }
+                        def g(x : i64) = while x < 3 do { let y : i64 = x; x = y + 1; }
+                        def main(args : vec<i64>) = {
+                          let x : i64 = 1;
+                          let y : i64 = 2;
+                          {
+                            let x : i64 = 5;
+                            let x : i64 = x + 1;
+                            let z : i64 = x;
+                            y = x;
+                          }
+                          let a : i64 = x;
+                          case y of { x => a = a + x; }
+                          let b : i64 = f(3) + g(0);
+                        }");
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(1));
+        assert_eq!(lookup(&eval, "y"), SimpleValue::VInt(6));
+        assert_eq!(lookup(&eval, "a"), SimpleValue::VInt(7));
+        assert_eq!(lookup(&eval, "b"), SimpleValue::VInt(3));
+        assert!(!eval.frames[eval.cur_frame].contains_key(&Symbol::new("z".to_string())));
+
+        let (_, r) = try_run("def main(args : vec<i64>) = {
+                                { let z : i64 = 1; }
+                                z = 2;
+                              }");
+        assert!(matches!(r.unwrap_err().error, RuntimeErrorKind::UnboundVariable(x) if x.name == "z"));
+    }
+
+    #[test]
+    fn test_globals() {
+        let eval = run("count : i64
+                        base : i64 = 10
+                        limit : i64 = base * 2 + twice(1)
+                        def twice(n : i64) = { count = count + 1; return 2 * n; }
+                        def shadow(base : i64) = { return base; }
+                        def main(args : vec<i64>) = {
+                          let x : i64 = twice(base) + shadow(1);
+                          let y : i64 = limit;
+                          let count : i64 = 100;
+                        }");
+        let global = |x: &str| eval.heap[eval.globals[&Symbol::new(x.to_string())]].clone();
+        assert_eq!(global("count"), SimpleValue::VInt(2));
+        assert_eq!(global("limit"), SimpleValue::VInt(22));
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(21));
+        assert_eq!(lookup(&eval, "y"), SimpleValue::VInt(22));
+        // The local shadows the global from then on
+        assert_eq!(lookup(&eval, "count"), SimpleValue::VInt(100));
+
+        let (_, r) = try_run("g : i64 = h
+                              def main(args : vec<i64>) = {}");
+        assert!(matches!(r.unwrap_err().error, RuntimeErrorKind::UnboundVariable(x) if x.name == "h"));
+        // `main`'s arguments are not bound yet
+        let (_, r) = try_run("n : i64 = len(args)
+                              def main(args : vec<i64>) = {}");
+        assert!(matches!(r.unwrap_err().error, RuntimeErrorKind::UnboundVariable(x) if x.name == "args"));
+    }
+
+    #[test]
+    fn test_deep() {
+        // Neither the loop nor the recursion may grow the native stack
+        let eval = run("def sum(n : i64) = {
+                          if n == 0 then return 0;
+                          return n + sum(n - 1);
+                        }
+                        def main(args : vec<i64>) = {
+                          let i : i64 = 0;
+                          while i < 1000000 do i = i + 1;
+                          let s : i64 = sum(100000);
+                        }");
+        assert_eq!(lookup(&eval, "i"), SimpleValue::VInt(1000000));
+        assert_eq!(lookup(&eval, "s"), SimpleValue::VInt(5000050000));
+    }
+
+    #[test]
+    fn test_long_expressions() {
+        // Neither may the evaluation of long or deeply nested expressions
+        let elems = vec!["1"; 5000].join(", ");
+        let sum = vec!["1"; 5000].join(" + ");
+        let eval = run(&format!("def main(args : vec<i64>) = {{
+                                   let v : vec<i64> = [{}];
+                                   let n : i64 = len(v);
+                                   let s : i64 = {};
+                                 }}", elems, sum));
+        assert_eq!(lookup(&eval, "n"), SimpleValue::VInt(5000));
+        assert_eq!(lookup(&eval, "s"), SimpleValue::VInt(5000));
+    }
+
+    #[test]
+    fn test_fuel() {
+        let src = "def main(args : vec<i64>) = {
+                     let x : i64 = 0;
+                     while true do x = x + 1;
+                   }";
+        let (eval, r) = try_run_with(src, |eval| eval.set_fuel(Some(100)));
+        let err = r.unwrap_err();
+        assert_eq!(err.error, RuntimeErrorKind::OutOfFuel { steps: 100 });
+        assert_eq!(&src[err.loc.0..err.loc.1], "x = x + 1;");
+        assert_eq!(lookup(&eval, "x"), SimpleValue::VInt(98));
+
+        let (_, r) = try_run_with(&src.replace("true", "x < 10"), |eval| eval.set_fuel(Some(100)));
+        assert!(r.is_ok());
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut observer = None;
+        let (eval, r) = try_run_with("def inc(p : i64*) = {
+                                        *p = *p + 1;
+                                      }
+                                      def main(args : vec<i64>) = {
+                                        let x : i64 = 1;
+                                        let y : i64 = inc(&x);
+                                        case x of { z => x = z * 10; }
+                                      }", |eval| {
+            let trace = Rc::new(RefCell::new(Trace::new(eval.state())));
+            eval.add_observer(trace.clone());
+            observer = Some(trace);
+        });
+        r.unwrap();
+        let trace = observer.unwrap();
+        let trace = trace.borrow();
+        // The final state is the evaluator's
+        let last = trace.state_at(trace.len());
+        assert_eq!(last.heap, eval.heap);
+        assert_eq!(last.frames, eval.frames);
+
+        // Replaying step by step agrees with reconstructing each step
+        let mut states = Vec::new();
+        trace.for_each_state(|_, st| {
+            states.push(st.clone());
+            Ok::<_, ()>(())
+        }).unwrap();
+        assert_eq!(states.len(), trace.len());
+        for (i, st) in states.iter().enumerate() {
+            assert_eq!(*st, trace.state_at(i));
         }
+
+        // Inside `inc` there are two frames, and `x` has not been bumped yet
+        let (i, _) = trace.steps().iter().enumerate()
+                          .find(|(_, step)| step.writes.contains(&Write::PopFrame)).unwrap();
+        let inside = trace.state_at(i);
+        assert_eq!(inside.frames.len(), 2);
+        let x = last.store()[&Symbol::new("x".to_string())];
+        assert_eq!(inside.heap[x], SimpleValue::VInt(1));
+        assert_eq!(trace.state_at(i + 1).heap[x], SimpleValue::VInt(2));
+    }
+
+    #[test]
+    fn test_json_trace() {
+        let src = "g : i64 = 7\ndef main(args : vec<i64>) = {\n  let p : (i64, bool) = (1, true);\n  let q : (i64, bool)* = &p;\n  g = 8;\n}";
+        let m = parse::parse_module(src).unwrap();
+        let (trace, _) = record_run(&m, Vec::new(), None, Vec::new()).unwrap();
+        let file = SourceFile::new("t.pl".to_string(), src.to_string());
+        let mut out = Vec::new();
+        trace.write_json(Some(&file), &mut out).unwrap();
+        let lines: Vec<_> = std::str::from_utf8(&out).unwrap().lines().collect();
+        // The body of `main`, its three statements, and the end of `main`
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[2],
+                   "{\"step\":2,\"loc\":{\"start\":79,\"end\":105,\"line\":4,\"column\":3},\"depth\":1,\
+                    \"globals\":{\"g\":{\"addr\":2,\"value\":7}},\
+                    \"store\":{\"args\":{\"addr\":1,\"value\":[]},\"p\":{\"addr\":3,\"value\":{\"tuple\":[1,1]}}},\
+                    \"changed\":[{\"addr\":4,\"value\":{\"addr\":3}}]}");
+        assert!(lines[4].contains("\"globals\":{\"g\":{\"addr\":2,\"value\":8}}"));
     }
 }
@@ -0,0 +1,229 @@
+use crate::language::plaia::abstraction::*;
+use plaia_language::language::plaia::ast::*;
+use std::fmt;
+
+// `Mod(m, r)` is the integers `m·k + r`: with `m = 0` just `r`, otherwise
+// those congruent to `r` modulo `m`, where `0 <= r < m`. Parities are the
+// congruences modulo 2.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Congruence {
+    Bottom,
+    Mod(i64, i64),
+}
+
+pub type CongruenceValue = AbsValue<Congruence>;
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+// The inverse of `a` modulo `m`, for coprime `a` and `m`
+fn inverse(a: i128, m: i128) -> i128 {
+    let (mut r0, mut r1, mut s0, mut s1) = (a.rem_euclid(m), m, 1, 0);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (s0, s1) = (s1, s0 - q * s1);
+    }
+    s0.rem_euclid(m)
+}
+
+impl Congruence {
+    // Computed on as i128, which products of two i64s fit in. A modulus
+    // too large for an i64 is given up on, and a constant too large for
+    // one cannot be the result of an operation that did not overflow.
+    fn new(m: i128, r: i128) -> Congruence {
+        let m = m.abs();
+        match m {
+            0 if r < i64::MIN as i128 || r > i64::MAX as i128 => Congruence::Bottom,
+            0 => Congruence::Mod(0, r as i64),
+            m if m > i64::MAX as i128 => Congruence::top(),
+            m => Congruence::Mod(m as i64, r.rem_euclid(m) as i64),
+        }
+    }
+
+    fn parts(self) -> Option<(i128, i128)> {
+        match self {
+            Congruence::Bottom => None,
+            Congruence::Mod(m, r) => Some((m as i128, r as i128)),
+        }
+    }
+
+    // The integers described by both, by the Chinese remainder theorem. When
+    // their modulus is too large for an i64 but more than one i64 is among
+    // them, they cannot be described, and the least of those is given instead.
+    fn intersection(&self, other: &Self) -> Result<Congruence, i64> {
+        let ((m1, r1), (m2, r2)) = match (self.parts(), other.parts()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Ok(Congruence::Bottom),
+        };
+        if m1 == 0 || m2 == 0 {
+            let (c, other) = if m1 == 0 { (*self, other) } else { (*other, self) };
+            return Ok(if c.leq(other) { c } else { Congruence::Bottom });
+        }
+        let g = gcd(m1, m2);
+        if (r2 - r1) % g != 0 {
+            return Ok(Congruence::Bottom);
+        }
+        let t = ((r2 - r1) / g).rem_euclid(m2 / g) * inverse(m1 / g, m2 / g) % (m2 / g);
+        let (m, r) = (m1 / g * m2, r1 + m1 * t);
+        if m <= i64::MAX as i128 {
+            return Ok(Congruence::new(m, r));
+        }
+        let (min, max) = (i64::MIN as i128, i64::MAX as i128);
+        let least = min + (r - min).rem_euclid(m);
+        match least {
+            least if least > max => Ok(Congruence::Bottom),
+            least if least + m > max => Ok(Congruence::constant(least as i64)),
+            least => Err(least as i64),
+        }
+    }
+}
+
+impl Lattice for Congruence {
+    fn bottom() -> Self {
+        Congruence::Bottom
+    }
+
+    fn top() -> Self {
+        Congruence::Mod(1, 0)
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (self.parts(), other.parts()) {
+            (Some((m1, r1)), Some((m2, r2))) => Congruence::new(gcd(gcd(m1, m2), r1 - r2), r1),
+            (None, _) => *other,
+            (_, None) => *self,
+        }
+    }
+
+    // Only a single integer is below both when their intersection cannot be
+    // described
+    fn meet(&self, other: &Self) -> Self {
+        self.intersection(other).unwrap_or_else(Congruence::constant)
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        match (self.parts(), other.parts()) {
+            (None, _) => true,
+            (_, None) => false,
+            (Some((m1, r1)), Some((0, r2))) => m1 == 0 && r1 == r2,
+            (Some((m1, r1)), Some((m2, r2))) => m1 % m2 == 0 && (r1 - r2) % m2 == 0,
+        }
+    }
+}
+
+impl NumDomain for Congruence {
+    fn constant(n: i64) -> Self {
+        Congruence::Mod(0, n)
+    }
+
+    fn as_constant(&self) -> Option<i64> {
+        match *self {
+            Congruence::Mod(0, r) => Some(r),
+            _ => None,
+        }
+    }
+
+    fn arith(o: &BinOp, a: &Self, b: &Self) -> Self {
+        let ((m1, r1), (m2, r2)) = match (a.parts(), b.parts()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Congruence::Bottom,
+        };
+        if let (Some(x), Some(y)) = (a.as_constant(), b.as_constant()) {
+            return arith_const(o, x, y).map_or(Congruence::Bottom, Congruence::constant);
+        }
+        match o {
+            BinOp::Add => Congruence::new(gcd(m1, m2), r1 + r2),
+            BinOp::Sub => Congruence::new(gcd(m1, m2), r1 - r2),
+            BinOp::Mul => Congruence::new(gcd(gcd(m1 * m2, m1 * r2), m2 * r1), r1 * r2),
+            BinOp::Div if m2 == 0 && r2 == 0 => Congruence::Bottom,
+            BinOp::Div if m1 == 0 && r1 == 0 => Congruence::constant(0),
+            // `c` divides every `m1·k + r1` exactly, so truncation is exact
+            BinOp::Div if m2 == 0 && m1 % r2 == 0 && r1 % r2 == 0 => Congruence::new(m1 / r2, r1 / r2),
+            _ => Congruence::top(),
+        }
+    }
+
+    fn assume(o: &BinOp, a: &Self, b: &Self, holds: bool) -> (Self, Self) {
+        if let (Some(x), Some(y)) = (a.as_constant(), b.as_constant()) {
+            return if compare_const(o, x, y) == holds { (*a, *b) } else { (Congruence::Bottom, Congruence::Bottom) };
+        }
+        match (o, holds, a.intersection(b)) {
+            (BinOp::Eq, true, Ok(c)) | (BinOp::Neq, false, Ok(c)) => (c, c),
+            _ => (*a, *b),
+        }
+    }
+}
+
+impl fmt::Display for Congruence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Congruence::Bottom => write!(f, "⊥"),
+            Congruence::Mod(0, r) => write!(f, "{}", r),
+            Congruence::Mod(1, _) => write!(f, "ℤ"),
+            Congruence::Mod(m, 0) => write!(f, "{}ℤ", m),
+            Congruence::Mod(m, r) => write!(f, "{}ℤ+{}", m, r),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::abstraction::tests::*;
+    use crate::language::plaia::analysis::*;
+    use crate::language::plaia::congruence::*;
+    use plaia_language::language::plaia::parse;
+
+    fn samples() -> Vec<Congruence> {
+        vec![Congruence::Bottom, Congruence::top(), Congruence::constant(0), Congruence::constant(3),
+             Congruence::constant(-2), Congruence::Mod(2, 0), Congruence::Mod(2, 1), Congruence::Mod(3, 1),
+             Congruence::Mod(4, 2), Congruence::Mod(6, 3),
+             // Whose intersection is too fine to describe
+             Congruence::Mod(1 << 62, 0), Congruence::Mod(3, 1)]
+    }
+
+    #[test]
+    fn test_laws() {
+        check_laws(&samples());
+    }
+
+    #[test]
+    fn test_sound() {
+        check_sound(&samples(), -7..=7);
+    }
+
+    #[test]
+    fn test_ops() {
+        let op = |o, a, b| Congruence::arith(&o, &a, &b);
+        assert_eq!(Congruence::constant(1).join(&Congruence::constant(7)), Congruence::Mod(6, 1));
+        assert_eq!(Congruence::Mod(4, 1).meet(&Congruence::Mod(6, 3)), Congruence::Mod(12, 9));
+        assert_eq!(Congruence::Mod(4, 1).meet(&Congruence::Mod(6, 2)), Congruence::Bottom);
+        // Both `i64::MIN` and `1 << 62` are congruent to 1 modulo 3
+        let (big, three) = (Congruence::Mod(1 << 62, 0), Congruence::Mod(3, 1));
+        assert_eq!(big.meet(&three), Congruence::constant(i64::MIN));
+        assert_eq!(Congruence::assume(&BinOp::Eq, &big, &three, true), (big, three));
+        assert_eq!(big.meet(&Congruence::Mod(3, 0)), Congruence::constant(0));
+        assert_eq!(op(BinOp::Add, Congruence::Mod(4, 1), Congruence::Mod(6, 2)), Congruence::Mod(2, 1));
+        assert_eq!(op(BinOp::Mul, Congruence::Mod(2, 1), Congruence::constant(3)), Congruence::Mod(6, 3));
+        assert_eq!(op(BinOp::Div, Congruence::Mod(6, 3), Congruence::constant(3)), Congruence::Mod(2, 1));
+        assert_eq!(op(BinOp::Div, Congruence::Mod(6, 1), Congruence::constant(3)), Congruence::top());
+        assert_eq!(Congruence::Mod(4, 2).to_string(), "4ℤ+2");
+    }
+
+    #[test]
+    fn test_stride() {
+        let src = "def main(args : vec<i64>) = {
+                     let v : vec<i64> = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+                     let i : i64 = 1;
+                     let s : i64 = 0;
+                     while i < len(v) do { s = s + v[i]; i = i + 3; }
+                     case i of { 5 => s = 0;, _ => s = 1; }
+                   }";
+        let a: Analysis<CongruenceValue> = analyze(&parse::parse_module(src).unwrap()).unwrap();
+        let (loc, i) = a.indices.iter().next().unwrap();
+        assert_eq!((&src[loc.0..loc.1], i.to_string()), ("v[i]", "3ℤ+1".into()));
+        // `i` is never 5
+        assert!(!a.points.keys().any(|loc| &src[loc.0..loc.1] == "s = 0;"));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::language::plaia::abstraction::*;
+use plaia_language::language::plaia::ast::*;
+use std::fmt;
+
+// Whether an integer is known before the program runs
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Constant {
+    Bottom,
+    Const(i64),
+    Top,
+}
+
+pub type ConstValue = AbsValue<Constant>;
+
+impl Lattice for Constant {
+    fn bottom() -> Self {
+        Constant::Bottom
+    }
+
+    fn top() -> Self {
+        Constant::Top
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (Constant::Bottom, c) | (c, Constant::Bottom) => c,
+            (a, b) if a == b => a,
+            _ => Constant::Top,
+        }
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (Constant::Top, c) | (c, Constant::Top) => c,
+            (a, b) if a == b => a,
+            _ => Constant::Bottom,
+        }
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        matches!((self, other), (Constant::Bottom, _) | (_, Constant::Top)) || self == other
+    }
+}
+
+impl NumDomain for Constant {
+    fn constant(n: i64) -> Self {
+        Constant::Const(n)
+    }
+
+    fn as_constant(&self) -> Option<i64> {
+        match *self {
+            Constant::Const(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn arith(o: &BinOp, a: &Self, b: &Self) -> Self {
+        match (o, *a, *b) {
+            (_, Constant::Bottom, _) | (_, _, Constant::Bottom) => Constant::Bottom,
+            (o, Constant::Const(x), Constant::Const(y)) => arith_const(o, x, y).map_or(Constant::Bottom, Constant::Const),
+            (BinOp::Mul, Constant::Const(0), _) | (BinOp::Mul, _, Constant::Const(0)) |
+            (BinOp::Div, Constant::Const(0), _) => Constant::Const(0),
+            _ => Constant::Top,
+        }
+    }
+
+    // Two constants either are in relation `o` or are not; otherwise only
+    // equality tells anything
+    fn assume(o: &BinOp, a: &Self, b: &Self, holds: bool) -> (Self, Self) {
+        match (*a, *b) {
+            (Constant::Const(x), Constant::Const(y)) if compare_const(o, x, y) != holds => {
+                (Constant::Bottom, Constant::Bottom)
+            }
+            (Constant::Const(_), Constant::Const(_)) => (*a, *b),
+            _ => match (o, holds) {
+                (BinOp::Eq, true) | (BinOp::Neq, false) => (a.meet(b), a.meet(b)),
+                _ => (*a, *b),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Bottom => write!(f, "⊥"),
+            Constant::Const(n) => write!(f, "{}", n),
+            Constant::Top => write!(f, "⊤"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::abstraction::tests::*;
+    use crate::language::plaia::analysis::*;
+    use crate::language::plaia::constant::*;
+    use plaia_language::language::plaia::parse;
+
+    const CONSTANTS: [Constant; 8] = [Constant::Bottom, Constant::Top, Constant::Const(0), Constant::Const(1),
+                                      Constant::Const(-1), Constant::Const(3),
+                                      Constant::Const(i64::MIN), Constant::Const(i64::MAX)];
+
+    #[test]
+    fn test_laws() {
+        check_laws(&CONSTANTS);
+    }
+
+    #[test]
+    fn test_sound() {
+        check_sound(&CONSTANTS, -3..=3);
+    }
+
+    #[test]
+    fn test_analysis() {
+        let src = "k : i64 = 4
+                   def sq(x : i64) = { return x * x; }
+                   def main(args : vec<i64>) = {
+                     let a : i64 = sq(k);
+                     let b : i64 = args[0];
+                     let c : i64 = b * 0;
+                     let p : i64* = &a;
+                     *p = *p + 1;
+                     case a of { 17 => b = 1;, _ => b = 2; }
+                     let t : (i64, i64) = (a, c);
+                     let d : i64 = t[1] + a;
+                   }";
+        let a: Analysis<ConstValue> = analyze(&parse::parse_module(src).unwrap()).unwrap();
+        let exit = a.exit.unwrap();
+        let show = |x: &str| exit[x].to_string();
+        assert_eq!((show("k"), show("a"), show("b"), show("c"), show("d")),
+                   ("4".into(), "17".into(), "1".into(), "0".into(), "17".into()));
+        assert_eq!(show("t"), "(17, 0)");
+        assert!(!a.points.keys().any(|loc| &src[loc.0..loc.1] == "b = 2;"));
+        let alarms: Vec<_> = a.alarms.iter().map(|(loc, _)| &src[loc.0..loc.1]).collect();
+        assert_eq!(alarms, ["args[0]"]);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::language::plaia::concrete::{SimpleAddr, ValCompute};
+use crate::language::plaia::error::RuntimeError;
+use crate::language::plaia::trace::*;
+use plaia_language::language::plaia::ast::*;
+use plaia_language::language::plaia::parse;
+use plaia_language::language::plaia::source::SourceFile;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+step, s [N]       go forward N steps (default 1)
+back, b [N]       go back N steps (default 1)
+continue, c       go forward to the next breakpoint
+rcontinue, rc     go back to the previous breakpoint
+goto, g N         go to step N
+break, br [LINE]  set a breakpoint on LINE, or list the breakpoints
+delete, d LINE    remove the breakpoint on LINE
+print, p EXPR     evaluate EXPR, e.g. `x`, `*p`, `v[i] + 1`, `&x`
+locals, l         print the variables of the current frame
+where, w          show the current statement
+help, h           show this message
+quit, q           leave the debugger";
+
+// Steps through a recorded `Trace`, in either direction, keeping the state
+// at the current step.
+pub struct Debugger<'t, V> {
+    trace: &'t Trace<V>,
+    file: &'t SourceFile,
+    // How the run ended, shown at the last step
+    error: Option<&'t RuntimeError>,
+    pos: usize,
+    state: State<V>,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl<'t, V: ValCompute<SimpleAddr>> Debugger<'t, V> {
+    pub fn new(trace: &'t Trace<V>, file: &'t SourceFile, error: Option<&'t RuntimeError>) -> Self {
+        Debugger {
+            trace,
+            file,
+            error,
+            pos: 0,
+            state: trace.state_at(0),
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn state(&self) -> &State<V> {
+        &self.state
+    }
+
+    fn last(&self) -> usize {
+        self.trace.len().saturating_sub(1)
+    }
+
+    fn line_of(&self, i: usize) -> usize {
+        self.file.position(self.trace.steps()[i].loc.0).line
+    }
+
+    pub fn goto(&mut self, i: usize) {
+        let i = i.min(self.last());
+        if i >= self.pos {
+            // Going forward only needs the writes in between
+            for step in &self.trace.steps()[self.pos..i] {
+                for w in &step.writes {
+                    self.state.apply(w);
+                }
+            }
+        } else {
+            self.state = self.trace.state_at(i);
+        }
+        self.pos = i;
+    }
+
+    pub fn add_breakpoint(&mut self, line: usize) {
+        self.breakpoints.insert(line);
+    }
+
+    // Move at least one step forward (or back), then stop at the first step
+    // on a line with a breakpoint, or at the end of the trace
+    pub fn continue_forward(&mut self) {
+        let next = (self.pos + 1..self.trace.len())
+            .find(|i| self.breakpoints.contains(&self.line_of(*i)))
+            .unwrap_or_else(|| self.last());
+        self.goto(next);
+    }
+
+    pub fn continue_back(&mut self) {
+        let prev = (0..self.pos).rev()
+            .find(|i| self.breakpoints.contains(&self.line_of(*i)))
+            .unwrap_or(0);
+        self.goto(prev);
+    }
+
+    // Evaluate a side-effect free expression in the current state
+    pub fn eval(&self, e: &Expr) -> Result<V, String> {
+        match &e.expr {
+            ExprKind::Lit(l) => Ok(V::from_lit(l)),
+            ExprKind::Var(x) => {
+                let l = self.addr_of(x)?;
+                Ok(self.state.heap[l].clone())
+            }
+            ExprKind::Unary(UnOp::Ref, e) => match &e.expr {
+                ExprKind::Var(x) => Ok(V::from_loc(&self.addr_of(x)?)),
+                _ => Err("can only take the address of a variable".to_string()),
+            },
+            ExprKind::Unary(UnOp::Deref, e) => {
+                let l = V::unwrap_loc(self.eval(e)?).map_err(|err| err.to_string())?;
+                self.state.heap.get(l).cloned().ok_or_else(|| format!("dangling pointer {}", l))
+            }
+            ExprKind::Unary(op, e) => V::unop(op, self.eval(e)?).map_err(|err| err.to_string()),
+            // As in the interpreter, only evaluate `e2` if `e1` does not
+            // already decide the result
+            ExprKind::Binary(op, e1, e2) if *op == BinOp::And || *op == BinOp::Or => {
+                let short = *op == BinOp::Or;
+                let v1 = self.eval(e1)?;
+                if V::is_true(&v1) == short {
+                    return Ok(V::from_lit(&Lit { lit: LiteralKind::LBool(short), loc: e1.loc }));
+                }
+                V::op(op, v1, self.eval(e2)?).map_err(|err| err.to_string())
+            }
+            ExprKind::Binary(op, e1, e2) => {
+                V::op(op, self.eval(e1)?, self.eval(e2)?).map_err(|err| err.to_string())
+            }
+            ExprKind::Tuple(es) => Ok(V::from_tuple(self.eval_all(es)?)),
+            ExprKind::Vector(es) => Ok(V::from_vector(self.eval_all(es)?)),
+            ExprKind::FunCall(_, _) => Err("cannot call functions in the debugger".to_string()),
+        }
+    }
+
+    fn eval_all(&self, es: &[Expr]) -> Result<Vec<V>, String> {
+        es.iter().map(|e| self.eval(e)).collect()
+    }
+
+    fn addr_of(&self, x: &Symbol) -> Result<SimpleAddr, String> {
+        self.state.lookup(x).ok_or_else(|| format!("no variable `{}` here", x.name))
+    }
+
+    fn show<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let loc = self.trace.steps()[self.pos].loc;
+        let text = self.file.snippet(loc).lines().next().unwrap_or("");
+        writeln!(out, "[{}/{}] {}: {}", self.pos, self.last(), self.file.location(loc), text)?;
+        if self.pos == self.last() {
+            match self.error {
+                Some(err) => writeln!(out, "{}", err.render(self.file))?,
+                None => writeln!(out, "program finished")?,
+            }
+        }
+        Ok(())
+    }
+
+    // Read commands from `input` until it ends or the user quits
+    pub fn run<I: BufRead, W: Write>(&mut self, input: I, out: &mut W) -> io::Result<()> {
+        if self.trace.is_empty() {
+            return writeln!(out, "nothing to debug");
+        }
+        self.show(out)?;
+        write!(out, "(plaia) ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            let (cmd, arg) = match line.split_once(char::is_whitespace) {
+                Some((cmd, arg)) => (cmd, arg.trim()),
+                None => (line, ""),
+            };
+            let count = || if arg.is_empty() { Ok(1) } else { arg.parse::<usize>() };
+
+            match cmd {
+                "" => {}
+                "step" | "s" => match count() {
+                    Ok(n) => {
+                        self.goto(self.pos.saturating_add(n));
+                        self.show(out)?;
+                    }
+                    Err(_) => writeln!(out, "expected a number of steps")?,
+                },
+                "back" | "b" => match count() {
+                    Ok(n) => {
+                        self.goto(self.pos.saturating_sub(n));
+                        self.show(out)?;
+                    }
+                    Err(_) => writeln!(out, "expected a number of steps")?,
+                },
+                "continue" | "c" => {
+                    self.continue_forward();
+                    self.show(out)?;
+                }
+                "rcontinue" | "rc" => {
+                    self.continue_back();
+                    self.show(out)?;
+                }
+                "goto" | "g" => match arg.parse::<usize>() {
+                    Ok(i) => {
+                        self.goto(i);
+                        self.show(out)?;
+                    }
+                    Err(_) => writeln!(out, "expected a step number")?,
+                },
+                "break" | "br" if arg.is_empty() => {
+                    for line in &self.breakpoints {
+                        writeln!(out, "{}:{}", self.file.name, line)?;
+                    }
+                }
+                "break" | "br" => match arg.parse::<usize>() {
+                    Ok(line) if line >= 1 && line <= self.file.line_count() => {
+                        self.add_breakpoint(line);
+                        writeln!(out, "breakpoint at {}:{}", self.file.name, line)?;
+                    }
+                    _ => writeln!(out, "expected a line between 1 and {}", self.file.line_count())?,
+                },
+                "delete" | "d" => match arg.parse::<usize>() {
+                    Ok(line) if self.breakpoints.remove(&line) => {}
+                    _ => writeln!(out, "no breakpoint on line `{}`", arg)?,
+                },
+                "print" | "p" => match parse::parse_expression(arg) {
+                    Ok(e) => match self.eval(&e) {
+                        Ok(v) => writeln!(out, "{:?}", v)?,
+                        Err(msg) => writeln!(out, "error: {}", msg)?,
+                    },
+                    Err(err) => writeln!(out, "{}", err)?,
+                },
+                "locals" | "l" => {
+                    let mut vars: Vec<_> = self.state.store().iter().collect();
+                    vars.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+                    for (x, l) in vars {
+                        writeln!(out, "{} = {:?}", x.name, self.state.heap[*l])?;
+                    }
+                }
+                "where" | "w" => {
+                    writeln!(out, "{}", self.file.annotate(self.trace.steps()[self.pos].loc))?;
+                }
+                "help" | "h" => writeln!(out, "{}", HELP)?,
+                "quit" | "q" => return Ok(()),
+                _ => writeln!(out, "unknown command `{}`, try `help`", cmd)?,
+            }
+            write!(out, "(plaia) ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::concrete::*;
+    use crate::language::plaia::debug::*;
+
+    const SRC: &str = "def main(args : vec<i64>) = {
+  let x : i64 = 1;
+  let p : i64* = &x;
+  while x < 4 do
+    *p = *p + 1;
+  let v : vec<i64> = [x, 2 * x];
+}";
+
+    fn session(commands: &str) -> String {
+        let m = parse::parse_module(SRC).unwrap();
+        let (trace, err) = record_run(&m, Vec::new(), None, Vec::new()).unwrap();
+        assert!(err.is_none());
+        let file = SourceFile::new("t.pl".to_string(), SRC.to_string());
+        let mut out = Vec::new();
+        Debugger::new(&trace, &file, None).run(commands.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_step_and_print() {
+        let out = session("s 4\np x\np *p\np &x\nb\np x\nl\np y\n");
+        assert!(out.starts_with("[0/8] t.pl:1:29: {\n(plaia) [4/8] t.pl:5:5: *p = *p + 1;\n"));
+        assert!(out.contains("[4/8] t.pl:5:5: *p = *p + 1;\n(plaia) VInt(1)\n(plaia) VInt(1)\n(plaia) VAddr(2)\n"));
+        assert!(out.contains("[3/8] t.pl:4:3: while x < 4 do\n"));
+        assert!(out.contains("args = VVector([])\np = VAddr(2)\nx = VInt(1)\n"));
+        assert!(out.contains("error: no variable `y` here"));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let out = session("br 5\nc\nc\np x\nrc\np x\nc\nc\nc\np v[1]\n");
+        let stops: Vec<_> = out.lines()
+                               .map(|l| l.trim_start_matches("(plaia) "))
+                               .filter(|l| l.contains("] t.pl"))
+                               .map(|l| &l[..l.find(']').unwrap() + 1])
+                               .collect();
+        assert_eq!(stops, ["[0/8]", "[4/8]", "[5/8]", "[4/8]", "[5/8]", "[6/8]", "[8/8]"]);
+        assert!(out.contains("program finished"));
+        assert!(out.contains("(plaia) VInt(2)\n"));
+        assert!(out.ends_with("(plaia) VInt(8)\n(plaia) \n"));
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let out = session("s 2\np x > 1 && v[5] == 0\np x == 1 || v[5] == 0\np x == 1 && v[5] == 0\n");
+        let answers: Vec<_> = out.lines().skip(2).map(|l| l.trim_start_matches("(plaia) ")).collect();
+        assert_eq!(answers[..2], ["VInt(0)", "VInt(1)"]);
+        assert!(answers[2].starts_with("error: "), "{}", answers[2]);
+    }
+}
@@ -0,0 +1,83 @@
+use plaia_language::language::plaia::ast::*;
+use plaia_language::language::plaia::source::SourceFile;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    Overflow,
+    IndexOutOfBounds { index: i64, len: usize },
+    UnboundVariable(Symbol),
+    UnknownFunction(Symbol),
+    ArityMismatch { f: Symbol, expected: usize, found: usize },
+    NotAPointer(String),
+    NotAnLValue,
+    TypeError(String),
+    NoMain,
+    InvalidArgument(String),
+    OutOfFuel { steps: u64 },
+    // Raised by abstract evaluators, which only know a description of the
+    // index and the length
+    IndexMayBeOutOfBounds { index: String, len: String },
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::DivisionByZero =>
+                write!(f, "division by zero"),
+            RuntimeErrorKind::Overflow =>
+                write!(f, "arithmetic overflow"),
+            RuntimeErrorKind::IndexOutOfBounds { index, len } =>
+                write!(f, "index {} out of bounds for length {}", index, len),
+            RuntimeErrorKind::UnboundVariable(x) =>
+                write!(f, "unbound variable `{}`", x.name),
+            RuntimeErrorKind::UnknownFunction(x) =>
+                write!(f, "unknown function `{}`", x.name),
+            RuntimeErrorKind::ArityMismatch { f: g, expected, found } =>
+                write!(f, "`{}` takes {} argument(s) but {} were given", g.name, expected, found),
+            RuntimeErrorKind::NotAPointer(v) =>
+                write!(f, "not a pointer: {}", v),
+            RuntimeErrorKind::NotAnLValue =>
+                write!(f, "expression cannot be assigned to or referenced"),
+            RuntimeErrorKind::TypeError(msg) =>
+                write!(f, "type error: {}", msg),
+            RuntimeErrorKind::NoMain =>
+                write!(f, "no `main` function"),
+            RuntimeErrorKind::InvalidArgument(arg) =>
+                write!(f, "invalid argument `{}`: expected an integer", arg),
+            RuntimeErrorKind::OutOfFuel { steps } =>
+                write!(f, "out of fuel after {} steps", steps),
+            RuntimeErrorKind::IndexMayBeOutOfBounds { index, len } =>
+                write!(f, "index {} may be out of bounds for length {}", index, len),
+        }
+    }
+}
+
+// `loc` is the innermost expression (or statement) being evaluated when the
+// error occurred, and `stack` lists the active calls, outermost first, as the
+// called function and the location of the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub error: RuntimeErrorKind,
+    pub loc: Loc,
+    pub stack: Vec<(Symbol, Loc)>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.error)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl RuntimeError {
+    pub fn render(&self, file: &SourceFile) -> String {
+        let mut out = format!("{}\n{}", self, file.annotate(self.loc));
+        for (f, loc) in self.stack.iter().rev() {
+            out.push_str(&format!("\n  in `{}`, called at {}", f.name, file.location(*loc)));
+        }
+        out
+    }
+}
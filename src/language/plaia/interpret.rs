@@ -1,215 +1,601 @@
+use crate::language::plaia::error::RuntimeErrorKind;
 use plaia_language::language::plaia::ast::*;
 use plaia_language::language::plaia::ast::{Statement};
+use std::rc::Rc;
 
-pub trait Evaluator<R>
+// Continuations are reference counted closures that only borrow from the
+// program being run, so that they can outlive the native stack frame that
+// created them. This lets an evaluator return the rest of the computation to
+// a driver loop instead of calling it, which keeps long blocks, loops and
+// deep recursion from overflowing the native stack.
+pub type Kont<'a, E, R> = Rc<dyn Fn(&mut E) -> R + 'a>;
+pub type ValKont<'a, E, V, R> = Rc<dyn Fn(&mut E, V) -> R + 'a>;
+pub type Thunk<'a, E, R> = Box<dyn FnOnce(&mut E) -> R + 'a>;
+
+// How to evaluate subexpressions and substatements
+pub type ExprRec<'a, E, V, R> = fn(&mut E, &'a Expr, ValKont<'a, E, V, R>) -> R;
+pub type StmtRec<'a, E, R> = fn(&mut E, &'a Statement, Kont<'a, E, R>, Kont<'a, E, R>) -> R;
+
+// The result of a trampolined computation: either finished, or with a next
+// step that should be run from the driver loop. An abstract evaluator that
+// follows several paths returns them with `Then`, and the driver runs each
+// in turn, keeping only the result of the last.
+pub enum Bounce<'a, E, T> {
+    Done(T),
+    More(Thunk<'a, E, Bounce<'a, E, T>>),
+    Then(Box<Bounce<'a, E, T>>, Box<Bounce<'a, E, T>>),
+}
+
+impl<'a, E, T> Bounce<'a, E, T> {
+    pub fn run(self, eval: &mut E) -> T {
+        let mut b = self;
+        let mut pending = Vec::new();
+        loop {
+            match b {
+                Bounce::Done(t) => match pending.pop() {
+                    Some(next) => b = next,
+                    None => return t,
+                },
+                Bounce::More(k) => b = k(eval),
+                Bounce::Then(first, rest) => {
+                    pending.push(*rest);
+                    b = *first;
+                }
+            }
+        }
+    }
+}
+
+impl<'a, E, T> From<Thunk<'a, E, Bounce<'a, E, T>>> for Bounce<'a, E, T> {
+    fn from(k: Thunk<'a, E, Bounce<'a, E, T>>) -> Self {
+        Bounce::More(k)
+    }
+}
+
+// Notified of what an evaluator does, e.g. to record a trace, or to count
+// how often each statement runs. The interpreter reports statements,
+// expression values, calls and returns; the evaluator reports changes to its
+// store and heap. Every hook does nothing by default.
+pub trait ExecutionObserver<V, L> {
+    fn enter_stmt(&mut self, _s: &Statement) {}
+    // After `s` completes, or leaves the function with a `return`
+    fn exit_stmt(&mut self, _s: &Statement) {}
+    fn expr_value(&mut self, _e: &Expr, _v: &V) {}
+    // Before the callee's frame is pushed
+    fn call(&mut self, _f: &Symbol, _args: &[(Symbol, V)]) {}
+    // After the callee's frame is popped
+    fn ret(&mut self, _f: &Symbol, _v: &V) {}
+    fn alloc(&mut self, _l: &L, _v: &V) {}
+    fn heap_write(&mut self, _l: &L, _v: &V) {}
+    fn store_write(&mut self, _x: &Symbol, _l: &L) {}
+    // `x` went out of scope, and is no longer bound in the current frame
+    fn store_remove(&mut self, _x: &Symbol) {}
+    fn global_write(&mut self, _x: &Symbol, _l: &L) {}
+}
+
+pub trait Evaluator<'a, R>
 {
-    type V : Clone;
-    type L : Clone;
+    type V : Clone + 'a;
+    type L : Clone + 'a;
     // V : Value
     // L : Location
     //
     // The store maps symbols (variables) to locations
     // The heap maps locations to values
-    fn find_store<K:?Sized>(&mut self, s: &Symbol, k: &K) -> R
-        where K: Fn(&mut Self, Self::L) -> R;
-    fn find_heap<K:?Sized>(&mut self, s: &Self::L, k: &K) -> R
-        where K: Fn(&mut Self, Self::V) -> R;
-
-    fn alloc<K:?Sized>(&mut self, k: &K) -> R
-        where K: Fn(&mut Self, Self::L) -> R;
-    fn update_store<K:?Sized>(&mut self, x: &Symbol, l: &Self::L, k: &K) -> R
-        where K: Fn(&mut Self) -> R;
-    fn update_heap<K:?Sized>(&mut self, l: &Self::L, v: Self::V, k: &K) -> R
-        where K: Fn(&mut Self) -> R;
-
-    fn push_frame<K:?Sized>(&mut self, frame: Vec<(Symbol, Self::V)>, k: &K) -> R
-    where K: Fn(&mut Self) -> R;
+    fn find_store<K>(&mut self, s: &Symbol, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::L) -> R;
+    fn find_heap<K>(&mut self, s: &Self::L, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R;
+
+    fn alloc<K>(&mut self, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::L) -> R;
+    fn update_store<K>(&mut self, x: &Symbol, l: &Self::L, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self) -> R;
+    // Bind `x` in the global scope, which `find_store` falls back to when `x`
+    // is not bound in the current frame
+    fn update_global<K>(&mut self, x: &Symbol, l: &Self::L, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self) -> R;
+    // Blocks and case arms open a scope: a declaration in it shadows any
+    // binding of the same name until the scope is exited
+    fn enter_scope(&mut self);
+    fn exit_scope(&mut self);
+    fn update_heap<K>(&mut self, l: &Self::L, v: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self) -> R;
+
+    // Each frame has its own return slot, found with `return_loc`
+    fn push_frame<K>(&mut self, f: &Symbol, frame: Vec<(Symbol, Self::V)>, k: &K) -> R
+    where K: ?Sized + Fn(&mut Self) -> R;
     fn pop_frame(&mut self);
     fn return_loc(&mut self) -> Self::L;
 
     // // "Meaning"
-    fn denote<K:?Sized>(&mut self, o: &BinOp, e1: Self::V, e2: Self::V, k: &K) -> R
-        where K: Fn(&mut Self, Self::V) -> R;
-    fn do_match<K:?Sized>(&mut self, p: &PatternKind, v: &Self::V, k: &K) -> R
+    fn denote<K>(&mut self, o: &BinOp, e1: Self::V, e2: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R;
+    // Unary operators other than `&` and `*`: `!`, `-` and `len`
+    fn denote_unary<K>(&mut self, o: &UnOp, e: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R;
+    // `agg` with the element at index `i` replaced by `v`
+    fn denote_update<K>(&mut self, agg: Self::V, i: Self::V, v: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R;
+    // `agg` with `v` appended
+    fn denote_push<K>(&mut self, agg: Self::V, v: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::V) -> R;
+    // Whether `v`, the value of `scrutinee`, matches `p`
+    fn do_match<K>(&mut self, scrutinee: &Expr, p: &PatternKind, v: &Self::V, k: &K) -> R
     where
-        K: Fn(&mut Self, bool) -> R;
+        K: ?Sized + Fn(&mut Self, bool) -> R;
+    // What `v`, the value of `scrutinee`, may still be after it failed to
+    // match `p`, for the arms that follow. Evaluators that know `v` exactly
+    // can keep it as it is.
+    fn unmatched(&self, _scrutinee: &Expr, _p: &PatternKind, v: &Self::V) -> Self::V {
+        v.clone()
+    }
+    // Decide which way a conditional goes, given the guard and its value
+    // (loop guards and the left operand of `&&` and `||`). Evaluators that
+    // cannot decide may call `k` once for each outcome.
+    fn branch<K>(&mut self, guard: &Expr, v: &Self::V, k: &K) -> R
+    where
+        K: ?Sized + Fn(&mut Self, bool) -> R;
+    // Called every time control reaches the head of the loop `s`, before its
+    // guard is evaluated (e.g. to widen the state there).
+    fn loop_head<K>(&mut self, s: &Statement, k: &K) -> R
+    where
+        K: ?Sized + Fn(&mut Self) -> R;
 
     fn inj_val(&self, v: &Lit) -> Self::V;
     fn inj_loc(&self, l: Self::L) -> Self::V;
-    fn unwrap_ptr(&self, v: Self::V) -> Self::L;
+    fn inj_tuple(&self, vs: Vec<Self::V>) -> Self::V;
+    fn inj_vector(&self, vs: Vec<Self::V>) -> Self::V;
+    fn unwrap_ptr<K>(&mut self, v: Self::V, k: &K) -> R
+        where K: ?Sized + Fn(&mut Self, Self::L) -> R;
+
+    fn fn_decl(&self, f: &Symbol) -> Option<&'a FnDecl>;
 
-    fn fn_decl(&self, f: &Symbol) -> FnDecl;
+    // Evaluation cannot continue along this path. A concrete evaluator stops
+    // with an error; an abstract one may record an alarm and go on exploring
+    // the paths that remain.
+    fn fail(&mut self, err: RuntimeErrorKind) -> R;
+
+    // Run `k`, either right away or, to keep the native stack shallow, by
+    // handing it back to the driver (see `Bounce`). The interpreter suspends
+    // before every statement, when returning from a call, and between the
+    // operands, elements and arguments of an expression.
+    fn suspend(&mut self, k: Thunk<'a, Self, R>) -> R;
+
+    // Pass each attached observer to `f`
+    fn observe<F>(&mut self, _f: F)
+    where
+        F: FnMut(&mut dyn ExecutionObserver<Self::V, Self::L>)
+    {
+    }
 }
 
-fn eval_lval<E,Kont,Rec:?Sized,R>(eval: &mut E, e: & Expr, r: & Rec, ret: &Kont) -> R
+fn eval_lval<'a,E,R>(eval: &mut E, e: &'a Expr, r: ExprRec<'a,E,E::V,R>, ret: ValKont<'a,E,E::L,R>) -> R
 where
-    E:    Evaluator<R>,
-    Rec:  Fn(&mut E, & Expr, &(dyn Fn(&mut E, E::V) -> R)) -> R,
-    Kont: ?Sized + Fn(&mut E, E::L) -> R,
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
 {
     match &e.expr {
-        ExprKind::Var(x)   => eval.find_store(&x, ret),
+        ExprKind::Var(x)   => eval.find_store(x, &*ret),
         ExprKind::Unary(UnOp::Deref, e) => {
-            r(eval, &e, &|eval1: &mut E, ptrval| {
-                let loc = eval1.unwrap_ptr(ptrval);
-                ret(eval1, loc)
-            })
+            r(eval, e, Rc::new(move |eval1: &mut E, ptrval| eval1.unwrap_ptr(ptrval, &*ret)))
         },
-        _ => panic!("Not an LVal!"), //TODO: There should be a `fail` trait method..or continuation?
+        _ => eval.fail(RuntimeErrorKind::NotAnLValue),
     }
 }
 
-pub fn eval_expr<E,StmtRec:?Sized,Rec:?Sized,Kont,R>(eval: &mut E, e: & Expr, r: & Rec, stmt_rec: & StmtRec, ret: & Kont) -> R
+// A place is an lval followed by a (possibly empty) path of indices into the
+// tuple or vector stored there, e.g. `v[i][j]` or `(*p)[0]`.
+type PlaceKont<'a, E, L, V, R> = Rc<dyn Fn(&mut E, L, Vec<V>) -> R + 'a>;
+
+fn eval_place<'a,E,R>(eval: &mut E, e: &'a Expr, r: ExprRec<'a,E,E::V,R>, ret: PlaceKont<'a,E,E::L,E::V,R>) -> R
 where
-    E:    Evaluator<R>,
-    Rec:  Fn(&mut E, & Expr, &(dyn Fn(&mut E, E::V) -> R)) -> R,
-    StmtRec: Fn(&mut E, &Statement, &(dyn Fn(&mut E) -> R)) -> R,
-    Kont: ?Sized + Fn(&mut E, E::V) -> R,
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
 {
+    match &e.expr {
+        ExprKind::Binary(BinOp::Proj, agg, idx) => {
+            eval_place(eval, agg, r, Rc::new(move |eval: &mut E, loc: E::L, path: Vec<E::V>| {
+                let ret = ret.clone();
+                r(eval, idx, Rc::new(move |eval: &mut E, i: E::V| {
+                    let mut path2 = path.clone();
+                    path2.push(i);
+                    ret(eval, loc.clone(), path2)
+                }))
+            }))
+        }
+        _ => eval_lval(eval, e, r, Rc::new(move |eval: &mut E, loc: E::L| ret(eval, loc, Vec::new()))),
+    }
+}
+
+fn read_path<'a,E,R>(eval: &mut E, agg: E::V, path: &[E::V], ret: &dyn Fn(&mut E, E::V) -> R) -> R
+where
+    E:    Evaluator<'a, R>,
+{
+    match path.split_first() {
+        None => ret(eval, agg),
+        Some((i, rest)) => {
+            eval.denote(&BinOp::Proj, agg, i.clone(), &|eval: &mut E, inner: E::V| {
+                read_path(eval, inner, rest, ret)
+            })
+        }
+    }
+}
+
+fn update_path<'a,E,R>(eval: &mut E, agg: E::V, path: &[E::V], v: E::V, ret: &dyn Fn(&mut E, E::V) -> R) -> R
+where
+    E:    Evaluator<'a, R>,
+{
+    match path.split_first() {
+        None => ret(eval, v),
+        Some((i, rest)) => {
+            eval.denote(&BinOp::Proj, agg.clone(), i.clone(), &|eval: &mut E, inner: E::V| {
+                update_path(eval, inner, rest, v.clone(), &|eval: &mut E, inner2: E::V| {
+                    eval.denote_update(agg.clone(), i.clone(), inner2, ret)
+                })
+            })
+        }
+    }
+}
+
+fn read_place<'a,E,R>(eval: &mut E, loc: &E::L, path: &[E::V], ret: &dyn Fn(&mut E, E::V) -> R) -> R
+where
+    E:    Evaluator<'a, R>,
+{
+    eval.find_heap(loc, &|eval: &mut E, root: E::V| read_path(eval, root, path, ret))
+}
+
+fn write_place<'a,E,Kont,R>(eval: &mut E, loc: &E::L, path: &[E::V], v: E::V, ret: &Kont) -> R
+where
+    E:    Evaluator<'a, R>,
+    Kont: ?Sized + Fn(&mut E) -> R,
+{
+    if path.is_empty() {
+        return eval.update_heap(loc, v, ret);
+    }
+    eval.find_heap(loc, &|eval: &mut E, root: E::V| {
+        update_path(eval, root, path, v.clone(), &|eval: &mut E, root2: E::V| {
+            eval.update_heap(loc, root2, ret)
+        })
+    })
+}
+
+pub fn eval_expr<'a,E,R>(eval: &mut E, e: &'a Expr, r: ExprRec<'a,E,E::V,R>, stmt_rec: StmtRec<'a,E,R>, ret: ValKont<'a,E,E::V,R>) -> R
+where
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
+{
+    let ret : ValKont<'a,E,E::V,R> = Rc::new(move |eval: &mut E, v: E::V| {
+        eval.observe(|o| o.expr_value(e, &v));
+        ret(eval, v)
+    });
     match &e.expr {
         ExprKind::Lit(l) => {
             let v = eval.inj_val(l);
             ret(eval, v)
         },
         ExprKind::Var(x) => {
-            eval.find_store(&x, &|eval: &mut E, l:E::L| {
-                eval.find_heap(&l, ret)
+            eval.find_store(x, &|eval: &mut E, l:E::L| {
+                eval.find_heap(&l, &*ret)
             })
         },
-        ExprKind::Binary(o, lhs, rhs) => {
-            let with_e1 = &move |eval: &mut E, lhsval: E::V| {
-                r(eval, &rhs, &move |eval: &mut E, rhsval: E::V| {
-                    eval.denote(&o, lhsval.clone(), rhsval, ret)
+        ExprKind::Binary(o, lhs, rhs) if *o == BinOp::And || *o == BinOp::Or => {
+            // Only evaluate `rhs` if `lhs` does not already decide the result
+            let short = *o == BinOp::Or;
+            r(eval, lhs, Rc::new(move |eval: &mut E, lhsval: E::V| {
+                eval.branch(lhs, &lhsval, &|eval: &mut E, b: bool| {
+                    if b == short {
+                        let v = eval.inj_val(&Lit { lit: LiteralKind::LBool(short), loc: lhs.loc });
+                        ret(eval, v)
+                    } else {
+                        let lhsval = lhsval.clone();
+                        let ret = ret.clone();
+                        r(eval, rhs, Rc::new(move |eval: &mut E, rhsval: E::V| {
+                            eval.denote(o, lhsval.clone(), rhsval, &*ret)
+                        }))
+                    }
                 })
-            };
-            r(eval, &lhs, with_e1)
+            }))
+        },
+        ExprKind::Binary(o, lhs, rhs) => {
+            // Operands may nest arbitrarily deep (e.g. `1 + 1 + ... + 1`),
+            // so suspend on the way into each of them
+            let with_e1 = Rc::new(move |eval: &mut E, lhsval: E::V| {
+                let ret = ret.clone();
+                eval.suspend(Box::new(move |eval: &mut E| {
+                    r(eval, rhs, Rc::new(move |eval: &mut E, rhsval: E::V| {
+                        eval.denote(o, lhsval.clone(), rhsval, &*ret)
+                    }))
+                }))
+            });
+            eval.suspend(Box::new(move |eval: &mut E| r(eval, lhs, with_e1)))
         },
         ExprKind::Unary(UnOp::Ref, e) => {
-            eval_lval(eval, e, r, &|eval1: &mut E, loc: E::L| {
+            eval_lval(eval, e, r, Rc::new(move |eval1: &mut E, loc: E::L| {
                 let ptr = eval1.inj_loc(loc);
                 ret(eval1, ptr)
-            })
+            }))
         }
         ExprKind::Unary(UnOp::Deref, e) => {
-            let k = &|eval: &mut E, ptr:E::V| {
+            let k = Rc::new(move |eval: &mut E, ptr:E::V| {
                 //  This continuation will get the result of calling `eval` on e.
-                //  This should be a pointer, so unwrap it:
-                let loc = &eval.unwrap_ptr(ptr);
-                // Now get the value at the unwrapped location
-                eval.find_heap(loc, ret)
-            };
-            r(eval, &e, k)
+                //  This should be a pointer, so unwrap it,
+                //  then get the value at the unwrapped location
+                eval.unwrap_ptr(ptr, &|eval: &mut E, loc: E::L| eval.find_heap(&loc, &*ret))
+            });
+            r(eval, e, k)
+        }
+        ExprKind::Unary(op, e) => {
+            r(eval, e, Rc::new(move |eval: &mut E, v: E::V| eval.denote_unary(op, v, &*ret)))
+        }
+        ExprKind::Tuple(es) => {
+            eval_exprs(eval, es, Vec::new(), r, Rc::new(move |eval: &mut E, vs: Vec<E::V>| {
+                let v = eval.inj_tuple(vs);
+                ret(eval, v)
+            }))
+        }
+        ExprKind::Vector(es) => {
+            eval_exprs(eval, es, Vec::new(), r, Rc::new(move |eval: &mut E, vs: Vec<E::V>| {
+                let v = eval.inj_vector(vs);
+                ret(eval, v)
+            }))
         }
-        ExprKind::Unary(_op, _e) => todo!(),
         ExprKind::FunCall(f, es) => {
-            let decl = eval.fn_decl(f);
-
-            let base : Box<(dyn Fn(&mut E, Vec<E::V>) -> R)>
-                = Box::new(move |e: &mut E, vs: Vec<E::V>| {
-                    // push args
-                    let in_frame : &(dyn Fn(&mut E) -> R)
-                        = &|e| {
-                            let body = Clone::clone(&decl.body);
-                            let with_stmt : &(dyn Fn(&mut E) -> R) = &|e| {
-                                e.pop_frame();
-                                let l = e.return_loc();
-                                e.find_heap(&l, ret)
-                            };
-                            stmt_rec(e, &body, with_stmt)
-                        };
-                    let args = decl.params
-                                   .iter()
-                                   .map(|tb| tb.name.clone())
-                                   .zip(vs)
-                                   .collect(); // TODO: Don't really need to do this?
-                    e.push_frame(args, in_frame)
+            let decl = match eval.fn_decl(f) {
+                Some(decl) => decl,
+                None => return eval.fail(RuntimeErrorKind::UnknownFunction(f.clone())),
+            };
+            if decl.params.len() != es.len() {
+                return eval.fail(RuntimeErrorKind::ArityMismatch {
+                    f: f.clone(),
+                    expected: decl.params.len(),
+                    found: es.len(),
                 });
+            }
 
-            let call_with_args = es.iter().rev().fold(base, |acc, arg_exp| {
-                Box::new(move |e, args| {
-                    r(e, arg_exp, &|e, v| {
-                        let mut args2 = args.clone();
-                        args2.push(v);
-                        acc(e, args2)
-                    })
-                })
-            });
+            eval_exprs(eval, es, Vec::new(), r, Rc::new(move |e: &mut E, vs: Vec<E::V>| {
+                // push args
+                let in_frame = |e: &mut E| {
+                    // Falling off the end of the body and `return`
+                    // both leave the result in this frame's return slot
+                    let ret = ret.clone();
+                    let with_stmt : Kont<'a, E, R> = Rc::new(move |e: &mut E| {
+                        // Returning can resume an arbitrarily deep chain of
+                        // callers without starting a new statement, so
+                        // suspend here too
+                        let ret = ret.clone();
+                        e.suspend(Box::new(move |e: &mut E| {
+                            let l = e.return_loc();
+                            e.pop_frame();
+                            e.find_heap(&l, &|e: &mut E, v: E::V| {
+                                e.observe(|o| o.ret(f, &v));
+                                ret(e, v)
+                            })
+                        }))
+                    });
+                    stmt_rec(e, &decl.body, with_stmt.clone(), with_stmt)
+                };
+                let args: Vec<_> = decl.params
+                                       .iter()
+                                       .map(|tb| tb.name.clone())
+                                       .zip(vs)
+                                       .collect();
+                e.observe(|o| o.call(f, &args));
+                e.push_frame(f, args, &in_frame)
+            }))
+        }
+    }
+}
 
-            call_with_args(eval, Vec::new())
+// Evaluate `es` from left to right and pass their values, after `vals`, to
+// `ret`, suspending between them so that long lists keep the stack shallow
+fn eval_exprs<'a,E,R>(eval: &mut E, es: &'a [Expr], vals: Vec<E::V>, r: ExprRec<'a,E,E::V,R>, ret: ValKont<'a,E,Vec<E::V>,R>) -> R
+where
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
+{
+    match es.split_first() {
+        None => ret(eval, vals),
+        Some((exp, rest)) => {
+            r(eval, exp, Rc::new(move |e: &mut E, v: E::V| {
+                let mut vals2 = vals.clone();
+                vals2.push(v);
+                let ret = ret.clone();
+                e.suspend(Box::new(move |e: &mut E| eval_exprs(e, rest, vals2, r, ret)))
+            }))
         }
     }
 }
 
-pub fn run_stmt<E,Kont:?Sized,EvalRec:?Sized,StmtRec:?Sized,R>(eval: &mut E, s: & Statement, expr_rec: & EvalRec, rec: & StmtRec, ret: &Kont) -> R
+// `ret` continues with the next statement, `exit` leaves the enclosing function
+// (after the result has been written to its return slot).
+pub fn run_stmt<'a,E,R>(eval: &mut E, s: &'a Statement, expr_rec: ExprRec<'a,E,E::V,R>, rec: StmtRec<'a,E,R>, ret: Kont<'a,E,R>, exit: Kont<'a,E,R>) -> R
 where
-    E: Evaluator<R>,
-    Kont: Fn(&mut E) -> R,
-    EvalRec: Fn(&mut E, &Expr, &(dyn Fn(&mut E, E::V) -> R)) -> R,
-    StmtRec: Fn(&mut E, &Statement, &(dyn Fn(&mut E) -> R)) -> R,
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
+{
+    eval.suspend(Box::new(move |eval: &mut E| {
+        eval.observe(|o| o.enter_stmt(s));
+        let ret : Kont<'a,E,R> = Rc::new(move |eval: &mut E| {
+            eval.observe(|o| o.exit_stmt(s));
+            ret(eval)
+        });
+        let exit : Kont<'a,E,R> = Rc::new(move |eval: &mut E| {
+            eval.observe(|o| o.exit_stmt(s));
+            exit(eval)
+        });
+        exec_stmt(eval, s, expr_rec, rec, ret, exit)
+    }))
+}
+
+fn exec_stmt<'a,E,R>(eval: &mut E, s: &'a Statement, expr_rec: ExprRec<'a,E,E::V,R>, rec: StmtRec<'a,E,R>, ret: Kont<'a,E,R>, exit: Kont<'a,E,R>) -> R
+where
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
 {
     match &s.stmt {
         StatementKind::Block(ss) => {
-            if ss.is_empty() {
-                ret(eval)
-            } else {
-                let base : Box<(dyn Fn(&mut E) -> R)>
-                    = Box::new(|e: &mut E| { ret(e) });
-
-                ss.iter().rev().fold(base, |acc, s| {
-                    Box::new(move |e: &mut E| {
-                        rec(e, &s, &|e: &mut E| {
-                            acc(e)
-                        })
-                    })
-                })(eval)
-            }
+            eval.enter_scope();
+            run_block(eval, ss, rec, scoped(ret), scoped(exit))
         }
 
         StatementKind::Assign(lhs, rhs) => {
-            expr_rec(eval, &rhs, &move |eval: &mut E, val: E::V| {
-                eval_lval(eval, &lhs, expr_rec, &move |eval, lval| eval.update_heap(&lval, val.clone(), ret))
-            })
+            expr_rec(eval, rhs, Rc::new(move |eval: &mut E, val: E::V| {
+                let ret = ret.clone();
+                eval_place(eval, lhs, expr_rec, Rc::new(move |eval: &mut E, loc: E::L, path: Vec<E::V>| {
+                    write_place(eval, &loc, &path, val.clone(), &*ret)
+                }))
+            }))
+        }
+
+        StatementKind::Push(target, elem) => {
+            expr_rec(eval, elem, Rc::new(move |eval: &mut E, val: E::V| {
+                let ret = ret.clone();
+                eval_place(eval, target, expr_rec, Rc::new(move |eval: &mut E, loc: E::L, path: Vec<E::V>| {
+                    read_place(eval, &loc, &path, &|eval: &mut E, agg: E::V| {
+                        eval.denote_push(agg, val.clone(), &|eval: &mut E, agg2: E::V| {
+                            write_place(eval, &loc, &path, agg2, &*ret)
+                        })
+                    })
+                }))
+            }))
         }
 
         StatementKind::VarDecl(tb, Some(exp)) => {
-            expr_rec(eval, &exp, &move |eval, value| {
+            expr_rec(eval, exp, Rc::new(move |eval: &mut E, value: E::V| {
                 eval.alloc(&|eval: &mut E, loc: E::L| {
                     eval.update_store(&tb.name, &loc, &|eval: &mut E| {
-                        eval.update_heap(&loc, value.clone(), ret)
+                        eval.update_heap(&loc, value.clone(), &*ret)
                     })
                 })
-            })
+            }))
         }
 
         StatementKind::VarDecl(tb, _) => {
             eval.alloc(&|eval: &mut E, loc: E::L| {
-                eval.update_store(&tb.name, &loc, ret)
+                eval.update_store(&tb.name, &loc, &*ret)
             })
         }
 
         StatementKind::Case(discr, branches) => {
-            expr_rec(eval, discr, &move |e, discrval| {
-                let base : Box<(dyn Fn(&mut E, bool) -> R)>
-                    = Box::new(|e: &mut E, _b: bool| { ret(e) });
-
-                branches.iter().fold(base, |acc, branch| {
-                    let CaseBranchKind::CaseArm(pat, stmt) = &branch.branch;
-                    let v = discrval.clone();
-                    Box::new(move |e, done| {
-                        if done {
-                            acc(e, done)
-                        } else {
-                            e.do_match(&pat.pattern, &v, &|e, b| {
-                                    if b {
-                                        rec(e, stmt, &|e| { acc(e, true) })
-                                    } else {
-                                        acc(e, false)
-                                    }
-                            })
-                        }
+            expr_rec(eval, discr, Rc::new(move |e: &mut E, discrval: E::V| {
+                run_case(e, discr, branches, discrval, rec, ret.clone(), exit.clone())
+            }))
+        }
+
+        StatementKind::While(guard, body) => {
+            run_loop(eval, s, guard, body, expr_rec, rec, ret, exit)
+        }
+
+        StatementKind::Return(exp) => {
+            expr_rec(eval, exp, Rc::new(move |eval: &mut E, val: E::V| {
+                let l = eval.return_loc();
+                eval.update_heap(&l, val, &*exit)
+            }))
+        }
+    }
+}
+
+// Exit the current scope before continuing with `k`
+fn scoped<'a,E,R>(k: Kont<'a,E,R>) -> Kont<'a,E,R>
+where
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
+{
+    Rc::new(move |eval: &mut E| {
+        eval.exit_scope();
+        k(eval)
+    })
+}
+
+fn run_block<'a,E,R>(eval: &mut E, ss: &'a [Statement], rec: StmtRec<'a,E,R>, ret: Kont<'a,E,R>, exit: Kont<'a,E,R>) -> R
+where
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
+{
+    match ss.split_first() {
+        None => ret(eval),
+        Some((s, rest)) => {
+            let exit2 = exit.clone();
+            rec(eval, s, Rc::new(move |e: &mut E| {
+                run_block(e, rest, rec, ret.clone(), exit2.clone())
+            }), exit)
+        }
+    }
+}
+
+// Allocate the globals in order, each initialized with its expression if it
+// has one (which may use the globals before it) and zero otherwise
+pub fn init_globals<'a,E,R>(eval: &mut E, gs: &'a [Global], expr_rec: ExprRec<'a,E,E::V,R>, ret: Kont<'a,E,R>) -> R
+where
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
+{
+    match gs.split_first() {
+        None => ret(eval),
+        Some((g, rest)) => {
+            let next = move |e: &mut E| init_globals(e, rest, expr_rec, ret.clone());
+            match &g.init {
+                Some(exp) => expr_rec(eval, exp, Rc::new(move |eval: &mut E, value: E::V| {
+                    eval.alloc(&|eval: &mut E, loc: E::L| {
+                        eval.update_global(&g.bind.name, &loc, &|eval: &mut E| {
+                            eval.update_heap(&loc, value.clone(), &next)
+                        })
                     })
-                })(e, false)
+                })),
+                None => eval.alloc(&|eval: &mut E, loc: E::L| {
+                    eval.update_global(&g.bind.name, &loc, &next)
+                }),
+            }
+        }
+    }
+}
+
+// Arms are tried in order; if none matches, continue after the `case`
+fn run_case<'a,E,R>(eval: &mut E, discr: &'a Expr, branches: &'a [CaseBranch], v: E::V, rec: StmtRec<'a,E,R>, ret: Kont<'a,E,R>, exit: Kont<'a,E,R>) -> R
+where
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
+{
+    match branches.split_first() {
+        None => ret(eval),
+        Some((branch, rest)) => {
+            let CaseBranchKind::CaseArm(pat, stmt) = &branch.branch;
+            // Variables bound by the pattern are only visible in the arm
+            eval.enter_scope();
+            eval.do_match(discr, &pat.pattern, &v, &|e: &mut E, b: bool| {
+                if b {
+                    rec(e, stmt, scoped(ret.clone()), scoped(exit.clone()))
+                } else {
+                    e.exit_scope();
+                    let v = e.unmatched(discr, &pat.pattern, &v);
+                    run_case(e, discr, rest, v, rec, ret.clone(), exit.clone())
+                }
             })
         }
     }
 }
+
+#[allow(clippy::too_many_arguments)]
+fn run_loop<'a,E,R>(eval: &mut E, s: &'a Statement, guard: &'a Expr, body: &'a Statement, expr_rec: ExprRec<'a,E,E::V,R>, rec: StmtRec<'a,E,R>, ret: Kont<'a,E,R>, exit: Kont<'a,E,R>) -> R
+where
+    E: Evaluator<'a, R> + 'a,
+    R: 'a,
+{
+    eval.loop_head(s, &|eval: &mut E| {
+        let (ret, exit) = (ret.clone(), exit.clone());
+        expr_rec(eval, guard, Rc::new(move |eval: &mut E, v: E::V| {
+            eval.branch(guard, &v, &|eval: &mut E, b: bool| {
+                if b {
+                    let (ret2, exit2) = (ret.clone(), exit.clone());
+                    let again : Kont<'a, E, R> = Rc::new(move |eval: &mut E| {
+                        run_loop(eval, s, guard, body, expr_rec, rec, ret2.clone(), exit2.clone())
+                    });
+                    rec(eval, body, again, exit.clone())
+                } else {
+                    ret(eval)
+                }
+            })
+        }))
+    })
+}
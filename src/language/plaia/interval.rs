@@ -0,0 +1,321 @@
+use crate::language::plaia::abstraction::*;
+use plaia_language::language::plaia::ast::*;
+use std::fmt;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone)]
+pub enum Bound {
+    NegInf,
+    Fin(i64),
+    PosInf,
+}
+
+// The integers between two bounds, inclusive, with `lo <= hi`
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Interval {
+    Bottom,
+    Range(Bound, Bound),
+}
+
+pub type IntervalValue = AbsValue<Interval>;
+
+// Bounds are computed as i128, which no operation on two i64 bounds
+// overflows, with the infinities at the ends
+const NEG_INF: i128 = i128::MIN;
+const POS_INF: i128 = i128::MAX;
+
+fn ext(b: Bound) -> i128 {
+    match b {
+        Bound::NegInf => NEG_INF,
+        Bound::Fin(n) => n as i128,
+        Bound::PosInf => POS_INF,
+    }
+}
+
+fn is_inf(n: i128) -> bool {
+    n == NEG_INF || n == POS_INF
+}
+
+fn ext_neg(n: i128) -> i128 {
+    match n {
+        NEG_INF => POS_INF,
+        POS_INF => NEG_INF,
+        n => -n,
+    }
+}
+
+// Only called on bounds that cannot be infinities of opposite signs
+fn ext_add(a: i128, b: i128) -> i128 {
+    if is_inf(a) { a } else if is_inf(b) { b } else { a + b }
+}
+
+fn ext_mul(a: i128, b: i128) -> i128 {
+    match (a, b) {
+        (0, _) | (_, 0) => 0,
+        (a, b) if is_inf(a) || is_inf(b) => if (a < 0) == (b < 0) { POS_INF } else { NEG_INF },
+        (a, b) => a * b,
+    }
+}
+
+// Truncating division by a nonzero `b`. A finite number divided by an
+// infinity is 0, and so (as a corner of a quotient's range) is ∞ / ∞.
+fn ext_div(a: i128, b: i128) -> i128 {
+    match (a, b) {
+        (_, b) if is_inf(b) => 0,
+        (a, b) if is_inf(a) => if (a < 0) == (b < 0) { POS_INF } else { NEG_INF },
+        (a, b) => a / b,
+    }
+}
+
+impl Interval {
+    // The i64s between `lo` and `hi`; finite bounds past the ends of i64 are
+    // cut off, as no value out there can be computed without overflowing
+    fn new(lo: i128, hi: i128) -> Interval {
+        let lo = if lo == NEG_INF { lo } else { lo.max(i64::MIN as i128) };
+        let hi = if hi == POS_INF { hi } else { hi.min(i64::MAX as i128) };
+        if lo > hi || lo > i64::MAX as i128 || hi < i64::MIN as i128 {
+            return Interval::Bottom;
+        }
+        let bound = |n: i128| match n {
+            NEG_INF => Bound::NegInf,
+            POS_INF => Bound::PosInf,
+            n => Bound::Fin(n as i64),
+        };
+        Interval::Range(bound(lo), bound(hi))
+    }
+
+    fn bounds(self) -> Option<(i128, i128)> {
+        match self {
+            Interval::Bottom => None,
+            Interval::Range(lo, hi) => Some((ext(lo), ext(hi))),
+        }
+    }
+
+    // The smallest interval containing all of `ns`
+    fn hull(ns: &[i128]) -> Interval {
+        Interval::new(*ns.iter().min().unwrap(), *ns.iter().max().unwrap())
+    }
+
+    // The bounds of `a o b` for arithmetic `o`, with the nonzero part of `b`
+    // when dividing
+    fn exact(o: &BinOp, (a1, a2): (i128, i128), (b1, b2): (i128, i128)) -> Interval {
+        match o {
+            BinOp::Add => Interval::new(ext_add(a1, b1), ext_add(a2, b2)),
+            BinOp::Sub => Interval::new(ext_add(a1, ext_neg(b2)), ext_add(a2, ext_neg(b1))),
+            BinOp::Mul => Interval::hull(&[ext_mul(a1, b1), ext_mul(a1, b2), ext_mul(a2, b1), ext_mul(a2, b2)]),
+            BinOp::Div => {
+                // Split the divisor into its negative and positive parts
+                let mut q = Interval::Bottom;
+                for (c, d) in [(b1, b2.min(-1)), (b1.max(1), b2)] {
+                    if c <= d {
+                        q = q.join(&Interval::hull(&[ext_div(a1, c), ext_div(a1, d), ext_div(a2, c), ext_div(a2, d)]));
+                    }
+                }
+                q
+            }
+            _ => Interval::new(NEG_INF, POS_INF),
+        }
+    }
+}
+
+impl Lattice for Interval {
+    fn bottom() -> Self {
+        Interval::Bottom
+    }
+
+    fn top() -> Self {
+        Interval::Range(Bound::NegInf, Bound::PosInf)
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (Interval::Bottom, i) | (i, Interval::Bottom) => i,
+            (Interval::Range(a, b), Interval::Range(c, d)) => Interval::Range(a.min(c), b.max(d)),
+        }
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        match (self.bounds(), other.bounds()) {
+            (Some((a, b)), Some((c, d))) => Interval::new(a.max(c), b.min(d)),
+            _ => Interval::Bottom,
+        }
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (Interval::Bottom, _) => true,
+            (_, Interval::Bottom) => false,
+            (Interval::Range(a, b), Interval::Range(c, d)) => c <= a && b <= d,
+        }
+    }
+
+    // Bounds that are still moving go to infinity
+    fn widen(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (Interval::Bottom, i) | (i, Interval::Bottom) => i,
+            (Interval::Range(a, b), Interval::Range(c, d)) => {
+                Interval::Range(if c < a { Bound::NegInf } else { a },
+                                if d > b { Bound::PosInf } else { b })
+            }
+        }
+    }
+
+    // Only infinite bounds are brought back in
+    fn narrow(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (Interval::Range(a, b), Interval::Range(c, d)) => {
+                Interval::Range(if a == Bound::NegInf { c } else { a },
+                                if b == Bound::PosInf { d } else { b })
+            }
+            _ => Interval::Bottom,
+        }
+    }
+}
+
+impl NumDomain for Interval {
+    fn constant(n: i64) -> Self {
+        Interval::Range(Bound::Fin(n), Bound::Fin(n))
+    }
+
+    fn as_constant(&self) -> Option<i64> {
+        match *self {
+            Interval::Range(Bound::Fin(a), Bound::Fin(b)) if a == b => Some(a),
+            _ => None,
+        }
+    }
+
+    fn arith(o: &BinOp, a: &Self, b: &Self) -> Self {
+        match (a.bounds(), b.bounds()) {
+            (Some(a), Some(b)) => Interval::exact(o, a, b),
+            _ => Interval::Bottom,
+        }
+    }
+
+    fn assume(o: &BinOp, a: &Self, b: &Self, holds: bool) -> (Self, Self) {
+        let o = if holds { o.clone() } else { negation(o) };
+        let (a_lo, b_hi) = match (a.bounds(), b.bounds()) {
+            (Some((a_lo, _)), Some((_, b_hi))) => (a_lo, b_hi),
+            _ => return (Interval::Bottom, Interval::Bottom),
+        };
+        let below = |hi: i128| Interval::new(NEG_INF, hi);
+        let above = |lo: i128| Interval::new(lo, POS_INF);
+        let (a, b) = match o {
+            BinOp::Lt => (a.meet(&below(ext_add(b_hi, -1))), b.meet(&above(ext_add(a_lo, 1)))),
+            BinOp::Lte => (a.meet(&below(b_hi)), b.meet(&above(a_lo))),
+            BinOp::Gt | BinOp::Gte => {
+                let (b, a) = Self::assume(&converse(&o), b, a, true);
+                (a, b)
+            }
+            BinOp::Eq => (a.meet(b), a.meet(b)),
+            // Only a constant can be cut off the end of the other side
+            BinOp::Neq => (a.without(b.as_constant()), b.without(a.as_constant())),
+            _ => (*a, *b),
+        };
+        if a.is_bottom() || b.is_bottom() { (Interval::Bottom, Interval::Bottom) } else { (a, b) }
+    }
+
+    // Whether the result may leave the i64s, taking infinite bounds to be the
+    // ends of i64
+    fn may_overflow(o: &BinOp, a: &Self, b: &Self) -> bool {
+        let clamp = |i: &Interval| i.bounds().map(|(lo, hi)| {
+            (lo.max(i64::MIN as i128), hi.min(i64::MAX as i128))
+        });
+        match (o, clamp(a), clamp(b)) {
+            (BinOp::Add, Some(a), Some(b)) | (BinOp::Sub, Some(a), Some(b)) |
+            (BinOp::Mul, Some(a), Some(b)) | (BinOp::Div, Some(a), Some(b)) => {
+                let products = || [a.0 * b.0, a.0 * b.1, a.1 * b.0, a.1 * b.1];
+                let (lo, hi) = match o {
+                    BinOp::Add => (a.0 + b.0, a.1 + b.1),
+                    BinOp::Sub => (a.0 - b.1, a.1 - b.0),
+                    BinOp::Mul => (*products().iter().min().unwrap(), *products().iter().max().unwrap()),
+                    // Only `i64::MIN / -1` overflows
+                    _ => return a.0 == i64::MIN as i128 && b.0 <= -1 && -1 <= b.1,
+                };
+                lo < i64::MIN as i128 || hi > i64::MAX as i128
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Interval {
+    // `self` without `n`, if that is one of its ends
+    fn without(self, n: Option<i64>) -> Interval {
+        match (self.bounds(), n) {
+            (Some((lo, hi)), Some(n)) if lo == n as i128 => Interval::new(lo + 1, hi),
+            (Some((lo, hi)), Some(n)) if hi == n as i128 => Interval::new(lo, hi - 1),
+            _ => self,
+        }
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::NegInf => write!(f, "-∞"),
+            Bound::Fin(n) => write!(f, "{}", n),
+            Bound::PosInf => write!(f, "+∞"),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interval::Bottom => write!(f, "⊥"),
+            Interval::Range(a, b) if a == b => write!(f, "{}", a),
+            Interval::Range(a, b) => write!(f, "[{}, {}]", a, b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::abstraction::tests::*;
+    use crate::language::plaia::interval::*;
+
+    fn range(lo: Bound, hi: Bound) -> Interval {
+        Interval::Range(lo, hi)
+    }
+
+    fn samples() -> Vec<Interval> {
+        use Bound::*;
+        vec![Interval::Bottom, Interval::top(), Interval::constant(0), Interval::constant(-3),
+             range(Fin(0), Fin(4)), range(Fin(-2), Fin(1)), range(Fin(1), Fin(3)), range(Fin(-4), Fin(-1)),
+             range(NegInf, Fin(0)), range(Fin(2), PosInf), range(Fin(i64::MAX - 1), PosInf),
+             range(NegInf, Fin(i64::MIN + 1))]
+    }
+
+    #[test]
+    fn test_laws() {
+        check_laws(&samples());
+    }
+
+    #[test]
+    fn test_sound() {
+        check_sound(&samples(), -5..=5);
+    }
+
+    #[test]
+    fn test_ops() {
+        use Bound::*;
+        let op = |o, a, b| Interval::arith(&o, &a, &b);
+        assert_eq!(op(BinOp::Add, range(Fin(0), PosInf), Interval::constant(1)), range(Fin(1), PosInf));
+        assert_eq!(op(BinOp::Mul, range(Fin(-2), Fin(3)), range(Fin(-4), Fin(1))), range(Fin(-12), Fin(8)));
+        assert_eq!(op(BinOp::Div, range(Fin(7), Fin(9)), range(Fin(-2), Fin(2))), range(Fin(-9), Fin(9)));
+        assert_eq!(op(BinOp::Div, range(Fin(7), Fin(9)), Interval::constant(0)), Interval::Bottom);
+        assert!(Interval::may_overflow(&BinOp::Add, &range(Fin(0), PosInf), &Interval::constant(1)));
+        assert!(!Interval::may_overflow(&BinOp::Add, &range(Fin(0), PosInf), &Interval::constant(0)));
+        assert!(Interval::may_overflow(&BinOp::Div, &Interval::top(), &range(Fin(-1), Fin(1))));
+
+        let lt = Interval::assume(&BinOp::Lt, &Interval::top(), &Interval::constant(10), true);
+        assert_eq!(lt, (range(NegInf, Fin(9)), Interval::constant(10)));
+        let ge = Interval::assume(&BinOp::Lt, &range(Fin(0), Fin(20)), &Interval::constant(10), false);
+        assert_eq!(ge, (range(Fin(10), Fin(20)), Interval::constant(10)));
+        let ne = Interval::assume(&BinOp::Neq, &range(Fin(0), Fin(20)), &Interval::constant(0), true);
+        assert_eq!(ne, (range(Fin(1), Fin(20)), Interval::constant(0)));
+
+        assert_eq!(range(Fin(0), Fin(1)).widen(&range(Fin(0), Fin(2))), range(Fin(0), PosInf));
+        assert_eq!(range(Fin(0), PosInf).narrow(&range(Fin(0), Fin(10))), range(Fin(0), Fin(10)));
+        assert_eq!(range(NegInf, Fin(3)).to_string(), "[-∞, 3]");
+    }
+}
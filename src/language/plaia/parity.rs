@@ -0,0 +1,130 @@
+use crate::language::plaia::abstraction::*;
+use plaia_language::language::plaia::ast::*;
+use std::fmt;
+
+// Whether an integer is even or odd
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Parity {
+    Bottom,
+    Even,
+    Odd,
+    Top,
+}
+
+pub type ParityValue = AbsValue<Parity>;
+
+impl Parity {
+    fn of(odd: bool) -> Parity {
+        if odd { Parity::Odd } else { Parity::Even }
+    }
+
+    // The parities a value may have, as whether it is odd
+    fn cases(self) -> Vec<bool> {
+        match self {
+            Parity::Bottom => vec![],
+            Parity::Even => vec![false],
+            Parity::Odd => vec![true],
+            Parity::Top => vec![false, true],
+        }
+    }
+}
+
+impl Lattice for Parity {
+    fn bottom() -> Self {
+        Parity::Bottom
+    }
+
+    fn top() -> Self {
+        Parity::Top
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (Parity::Bottom, p) | (p, Parity::Bottom) => p,
+            (a, b) if a == b => a,
+            _ => Parity::Top,
+        }
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (Parity::Top, p) | (p, Parity::Top) => p,
+            (a, b) if a == b => a,
+            _ => Parity::Bottom,
+        }
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        matches!((self, other), (Parity::Bottom, _) | (_, Parity::Top)) || self == other
+    }
+}
+
+impl NumDomain for Parity {
+    fn constant(n: i64) -> Self {
+        Parity::of(n % 2 != 0)
+    }
+
+    // The parity of a quotient depends on more than those of its operands
+    fn arith(o: &BinOp, a: &Self, b: &Self) -> Self {
+        let mut r = Parity::Bottom;
+        for x in a.cases() {
+            for y in b.cases() {
+                r = r.join(&match o {
+                    BinOp::Add | BinOp::Sub => Parity::of(x != y),
+                    BinOp::Mul => Parity::of(x && y),
+                    _ => Parity::Top,
+                });
+            }
+        }
+        r
+    }
+}
+
+impl fmt::Display for Parity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Parity::Bottom => "⊥",
+            Parity::Even => "even",
+            Parity::Odd => "odd",
+            Parity::Top => "⊤",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::abstraction::tests::*;
+    use crate::language::plaia::analysis::*;
+    use crate::language::plaia::parity::*;
+    use plaia_language::language::plaia::parse;
+
+    const PARITIES: [Parity; 4] = [Parity::Bottom, Parity::Even, Parity::Odd, Parity::Top];
+
+    #[test]
+    fn test_laws() {
+        check_laws(&PARITIES);
+    }
+
+    #[test]
+    fn test_sound() {
+        check_sound(&PARITIES, -5..=5);
+        assert_eq!(Parity::arith(&BinOp::Mul, &Parity::Odd, &Parity::Top), Parity::Top);
+        assert_eq!(Parity::arith(&BinOp::Sub, &Parity::Odd, &Parity::Odd), Parity::Even);
+        assert_eq!(Parity::arith(&BinOp::Div, &Parity::Even, &Parity::Bottom), Parity::Bottom);
+    }
+
+    #[test]
+    fn test_stride() {
+        let src = "def main(args : vec<i64>) = {
+                     let v : vec<i64> = [1, 2, 3, 4, 5, 6];
+                     let i : i64 = 0;
+                     while i < len(v) do { v[i] = v[i + 1] * 2; i = i + 2; }
+                   }";
+        let a: Analysis<ParityValue> = analyze(&parse::parse_module(src).unwrap()).unwrap();
+        let at = |s: &str| a.indices.iter().find(|(loc, _)| &src[loc.0..loc.1] == s).unwrap().1.to_string();
+        assert_eq!(at("v[i] = v[i + 1] * 2;"), "even");
+        assert_eq!(at("v[i + 1]"), "odd");
+        assert_eq!(a.exit.unwrap()["i"].to_string(), "even");
+    }
+}
@@ -0,0 +1,112 @@
+use crate::language::plaia::interpret::ExecutionObserver;
+use plaia_language::language::plaia::ast::*;
+use plaia_language::language::plaia::source::SourceFile;
+use std::collections::{BTreeMap, HashMap};
+
+// Counts how often each statement runs and each function is called. The
+// statements that were never counted are the ones a run did not cover.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub stmts: HashMap<Loc, u64>,
+    pub calls: HashMap<Symbol, u64>,
+}
+
+impl<V, L> ExecutionObserver<V, L> for Profile {
+    fn enter_stmt(&mut self, s: &Statement) {
+        *self.stmts.entry(s.loc).or_insert(0) += 1;
+    }
+
+    fn call(&mut self, f: &Symbol, _args: &[(Symbol, V)]) {
+        *self.calls.entry(f.clone()).or_insert(0) += 1;
+    }
+}
+
+fn stmt_locs(s: &Statement, locs: &mut Vec<Loc>) {
+    locs.push(s.loc);
+    match &s.stmt {
+        StatementKind::Block(ss) => ss.iter().for_each(|s| stmt_locs(s, locs)),
+        StatementKind::While(_, body) => stmt_locs(body, locs),
+        StatementKind::Case(_, branches) => {
+            for b in branches {
+                let CaseBranchKind::CaseArm(_, s) = &b.branch;
+                stmt_locs(s, locs);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl Profile {
+    // The statements of `m` that never ran, in source order
+    pub fn uncovered(&self, m: &Module) -> Vec<Loc> {
+        let mut locs = Vec::new();
+        for f in &m.functions {
+            stmt_locs(&f.body, &mut locs);
+        }
+        locs.retain(|loc| !self.stmts.contains_key(loc));
+        locs.sort_unstable();
+        locs
+    }
+
+    pub fn report(&self, m: &Module, file: &SourceFile) -> String {
+        let mut out = String::from("Calls:\n");
+        let calls: BTreeMap<_, _> = self.calls.iter().map(|(f, n)| (&f.name, n)).collect();
+        for (f, n) in calls {
+            out.push_str(&format!("{:>10}  {}\n", n, f));
+        }
+
+        out.push_str("Statements:\n");
+        let stmts: BTreeMap<_, _> = self.stmts.iter().collect();
+        for (loc, n) in stmts {
+            out.push_str(&format!("{:>10}  {}: {}\n", n, file.location(*loc), file.first_line(*loc)));
+        }
+
+        let uncovered = self.uncovered(m);
+        if !uncovered.is_empty() {
+            out.push_str("Never executed:\n");
+            for loc in uncovered {
+                out.push_str(&format!("{:>10}  {}: {}\n", "", file.location(loc), file.first_line(loc)));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::concrete::*;
+    use crate::language::plaia::profile::*;
+    use plaia_language::language::plaia::parse;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_profile() {
+        let src = "def f(x : i64) = {
+                     if x > 100 then return 0;
+                     return x;
+                   }
+                   def main(args : vec<i64>) = {
+                     let i : i64 = 0;
+                     while i < 3 do i = f(i) + 1;
+                     i = i + 1;
+                   }";
+        let m = parse::parse_module(src).unwrap();
+        let profile = Rc::new(RefCell::new(Profile::default()));
+        let (_, err) = record_run(&m, Vec::new(), None, vec![profile.clone()]).unwrap();
+        assert!(err.is_none());
+
+        let profile = profile.borrow();
+        assert_eq!(profile.calls[&Symbol::new("f".to_string())], 3);
+        let count = |snippet: &str| {
+            let lo = src.find(snippet).unwrap();
+            profile.stmts[&(lo, lo + snippet.len())]
+        };
+        assert_eq!(count("i = f(i) + 1;"), 3);
+        assert_eq!(count("return x;"), 3);
+        assert_eq!(count("i = i + 1;"), 1);
+
+        let uncovered: Vec<_> = profile.uncovered(&m).iter().map(|loc| &src[loc.0..loc.1]).collect();
+        assert_eq!(uncovered, ["return 0;"]);
+    }
+}
@@ -1,73 +1,231 @@
-// use crate::language::plaia::concrete::*;
-// use plaia_language::language::plaia::ast::*;
-
-
-// #[derive(PartialEq, Eq, Debug, Copy, Clone)]
-// pub enum SignedValue {
-//     VPos,
-//     VNeg,
-//     VZero,
-//     VUnknown,
-//     VAddr(SimpleAddr),
-// }
-
-// impl ValCompute<SimpleAddr> for SignedValue {
-//     fn zero() -> Self
-//     {
-//         SignedValue::VZero
-//     }
-
-//     fn is_true(_v: &Self) -> bool {
-//         todo!()
-//     }
-
-//     fn op(o: &BinOp, e1: Self, e2: Self) -> Self {
-//         match (o, e1, e2) {
-//             (BinOp::Add, v1, v2) if v1 == v2    => v1,
-//             (BinOp::Add, SignedValue::VZero, x) => x,
-//             (BinOp::Add, x, SignedValue::VZero) => x,
-
-//             (BinOp::Sub, SignedValue::VZero, SignedValue::VPos) => SignedValue::VNeg,
-//             (BinOp::Sub, SignedValue::VZero, SignedValue::VNeg) => SignedValue::VPos,
-//             (BinOp::Sub, SignedValue::VZero, SignedValue::VZero) => SignedValue::VZero,
-//             (BinOp::Sub, SignedValue::VPos,  SignedValue::VNeg) => SignedValue::VPos,
-//             (BinOp::Sub, SignedValue::VNeg,  SignedValue::VPos) => SignedValue::VNeg,
-
-//             (BinOp::Mul, x, y) if x == Self::zero() || y == Self::zero() => SignedValue::VZero,
-//             (BinOp::Mul, SignedValue::VPos, x) => x,
-//             (BinOp::Mul, SignedValue::VNeg, SignedValue::VNeg) => SignedValue::VPos,
-//             (BinOp::Mul, SignedValue::VNeg, SignedValue::VPos) => SignedValue::VNeg,
-
-//             (BinOp::Div, _, SignedValue::VUnknown) => SignedValue::VUnknown,
-//             (BinOp::Div, SignedValue::VZero, _) => SignedValue::VZero,
-//             (BinOp::Div, x, SignedValue::VPos) => x,
-//             (BinOp::Div, SignedValue::VNeg, SignedValue::VNeg) => SignedValue::VPos,
-//             (BinOp::Div, SignedValue::VPos, SignedValue::VNeg) => SignedValue::VNeg,
-//             _ => SignedValue::VUnknown
-//         }
-//     }
-
-//     fn unwrap_loc(v: Self) -> SimpleAddr
-//     {
-//         if let SignedValue::VAddr(a) = v {
-//             a
-//         } else {
-//             panic!("Not an address!")
-//         }
-//     }
-
-//     fn from_loc(l: &SimpleAddr) -> Self
-//     {
-//         SignedValue::VAddr(*l)
-//     }
-
-//     fn from_lit(l: &Lit) -> Self
-//     {
-//         match l.lit {
-//             LiteralKind::LInt(v) if v > 0 => SignedValue::VPos,
-//             LiteralKind::LInt(_)          => SignedValue::VZero,
-//             LiteralKind::LBool(true)      => SignedValue::VPos,
-//             LiteralKind::LBool(false)     => SignedValue::VZero
-//         }
-//     }
-// }
+use crate::language::plaia::abstraction::*;
+use plaia_language::language::plaia::ast::*;
+use std::fmt;
+
+// The sign of an integer, or of each integer in a set
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Sign {
+    Bottom,
+    Neg,
+    Zero,
+    Pos,
+    NonPos,
+    NonNeg,
+    NonZero,
+    Top,
+}
+
+// Values whose integers are only known by their sign
+pub type SignedValue = AbsValue<Sign>;
+
+// A sign is the set of the atoms below, as a bit mask
+const NEG: u8 = 1;
+const ZERO: u8 = 2;
+const POS: u8 = 4;
+const ATOMS: [u8; 3] = [NEG, ZERO, POS];
+
+impl Sign {
+    fn bits(self) -> u8 {
+        match self {
+            Sign::Bottom => 0,
+            Sign::Neg => NEG,
+            Sign::Zero => ZERO,
+            Sign::Pos => POS,
+            Sign::NonPos => NEG | ZERO,
+            Sign::NonNeg => ZERO | POS,
+            Sign::NonZero => NEG | POS,
+            Sign::Top => NEG | ZERO | POS,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Sign {
+        match bits {
+            0 => Sign::Bottom,
+            NEG => Sign::Neg,
+            ZERO => Sign::Zero,
+            POS => Sign::Pos,
+            3 => Sign::NonPos,
+            6 => Sign::NonNeg,
+            5 => Sign::NonZero,
+            _ => Sign::Top,
+        }
+    }
+
+    fn atoms(self) -> impl Iterator<Item = u8> {
+        ATOMS.iter().copied().filter(move |a| self.bits() & a != 0)
+    }
+
+    // Apply `f` to every pair of atoms and join the results
+    fn lift2(a: Sign, b: Sign, f: impl Fn(u8, u8) -> u8) -> Sign {
+        let bits = a.atoms().flat_map(|x| b.atoms().map(move |y| (x, y)))
+                    .fold(0, |bits, (x, y)| bits | f(x, y));
+        Sign::from_bits(bits)
+    }
+}
+
+fn negate(a: u8) -> u8 {
+    match a {
+        NEG => POS,
+        POS => NEG,
+        a => a,
+    }
+}
+
+fn add(x: u8, y: u8) -> u8 {
+    match (x, y) {
+        (ZERO, a) | (a, ZERO) => a,
+        (a, b) if a == b => a,
+        _ => NEG | ZERO | POS,
+    }
+}
+
+fn mul(x: u8, y: u8) -> u8 {
+    match (x, y) {
+        (ZERO, _) | (_, ZERO) => ZERO,
+        (a, b) if a == b => POS,
+        _ => NEG,
+    }
+}
+
+// Division truncates towards zero, so e.g. `1 / 2` is 0
+fn div(x: u8, y: u8) -> u8 {
+    match (x, y) {
+        (_, ZERO) => 0,
+        (ZERO, _) => ZERO,
+        (a, b) if a == b => ZERO | POS,
+        _ => NEG | ZERO,
+    }
+}
+
+// Whether `x o y` holds for some integers with the signs `x` and `y`. Two
+// numbers of the same nonzero sign can be in any order.
+fn possible(o: &BinOp, x: u8, y: u8) -> bool {
+    let rank = |a: u8| a.trailing_zeros();
+    match o {
+        BinOp::Lt => rank(x) < rank(y) || (x == y && x != ZERO),
+        BinOp::Lte => rank(x) <= rank(y),
+        BinOp::Gt => possible(&BinOp::Lt, y, x),
+        BinOp::Gte => possible(&BinOp::Lte, y, x),
+        BinOp::Eq => x == y,
+        BinOp::Neq => !(x == ZERO && y == ZERO),
+        _ => true,
+    }
+}
+
+impl Lattice for Sign {
+    fn bottom() -> Self {
+        Sign::Bottom
+    }
+
+    fn top() -> Self {
+        Sign::Top
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        Sign::from_bits(self.bits() | other.bits())
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        Sign::from_bits(self.bits() & other.bits())
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        self.bits() & !other.bits() == 0
+    }
+}
+
+impl NumDomain for Sign {
+    fn constant(n: i64) -> Self {
+        match n {
+            n if n < 0 => Sign::Neg,
+            0 => Sign::Zero,
+            _ => Sign::Pos,
+        }
+    }
+
+    fn arith(o: &BinOp, a: &Self, b: &Self) -> Self {
+        match o {
+            BinOp::Add => Sign::lift2(*a, *b, add),
+            BinOp::Sub => Sign::lift2(*a, *b, |x, y| add(x, negate(y))),
+            BinOp::Mul => Sign::lift2(*a, *b, mul),
+            BinOp::Div => Sign::lift2(*a, *b, div),
+            _ => Sign::Top,
+        }
+    }
+
+    fn as_constant(&self) -> Option<i64> {
+        if *self == Sign::Zero { Some(0) } else { None }
+    }
+
+    fn neg(a: &Self) -> Self {
+        Sign::from_bits(a.atoms().fold(0, |bits, x| bits | negate(x)))
+    }
+
+    // Keep the atoms of each side that the other side has a witness for
+    fn assume(o: &BinOp, a: &Self, b: &Self, holds: bool) -> (Self, Self) {
+        let o = if holds { o.clone() } else { negation(o) };
+        let keep = |xs: Sign, ys: Sign, o: &BinOp| {
+            Sign::from_bits(xs.atoms().filter(|x| ys.atoms().any(|y| possible(o, *x, y)))
+                              .fold(0, |bits, x| bits | x))
+        };
+        let a2 = keep(*a, *b, &o);
+        let b2 = keep(*b, a2, &converse(&o));
+        (a2, b2)
+    }
+}
+
+impl fmt::Display for Sign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Sign::Bottom => "⊥",
+            Sign::Neg => "<0",
+            Sign::Zero => "0",
+            Sign::Pos => ">0",
+            Sign::NonPos => "≤0",
+            Sign::NonNeg => "≥0",
+            Sign::NonZero => "≠0",
+            Sign::Top => "⊤",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language::plaia::abstraction::tests::*;
+    use crate::language::plaia::concrete::ValCompute;
+    use crate::language::plaia::signed::*;
+
+    const SIGNS: [Sign; 8] = [Sign::Bottom, Sign::Neg, Sign::Zero, Sign::Pos,
+                              Sign::NonPos, Sign::NonNeg, Sign::NonZero, Sign::Top];
+
+    #[test]
+    fn test_laws() {
+        check_laws(&SIGNS);
+        assert_eq!(Sign::Neg.join(&Sign::Zero), Sign::NonPos);
+        assert_eq!(Sign::NonNeg.meet(&Sign::NonZero), Sign::Pos);
+    }
+
+    #[test]
+    fn test_sound() {
+        check_sound(&SIGNS, -4..=4);
+    }
+
+    #[test]
+    fn test_ops() {
+        let op = |o, a, b| Sign::arith(&o, &a, &b);
+        assert_eq!(op(BinOp::Add, Sign::Pos, Sign::NonNeg), Sign::Pos);
+        assert_eq!(op(BinOp::Sub, Sign::Zero, Sign::NonPos), Sign::NonNeg);
+        assert_eq!(op(BinOp::Mul, Sign::NonZero, Sign::Neg), Sign::NonZero);
+        assert_eq!(op(BinOp::Div, Sign::Pos, Sign::Zero), Sign::Bottom);
+        assert_eq!(op(BinOp::Div, Sign::Neg, Sign::Pos), Sign::NonPos);
+        assert_eq!(Sign::assume(&BinOp::Lt, &Sign::Top, &Sign::Zero, true), (Sign::Neg, Sign::Zero));
+        assert_eq!(Sign::assume(&BinOp::Lt, &Sign::Top, &Sign::Zero, false), (Sign::NonNeg, Sign::Zero));
+        assert_eq!(Sign::assume(&BinOp::Neq, &Sign::NonNeg, &Sign::Zero, true), (Sign::Pos, Sign::Zero));
+
+        let v = |s| SignedValue::Num(s);
+        assert!(SignedValue::op(&BinOp::Div, v(Sign::Pos), v(Sign::Zero)).is_err());
+        assert_eq!(SignedValue::abs_op(&BinOp::Lt, &v(Sign::Neg), &v(Sign::NonNeg)), v(Sign::Pos));
+        assert_eq!(SignedValue::abs_op(&BinOp::Eq, &v(Sign::Pos), &v(Sign::Pos)), v(Sign::NonNeg));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::language::plaia::concrete::{SimpleAddr, SimpleStore};
+use crate::language::plaia::interpret::ExecutionObserver;
+use plaia_language::language::plaia::ast::*;
+use plaia_language::language::plaia::source::SourceFile;
+use std::collections::BTreeMap;
+use std::io;
+
+// The state of a `ConcreteEvaluator` that a trace can reconstruct: the heap,
+// the globals, and a store per active call (innermost last).
+#[derive(Debug, Clone, PartialEq)]
+pub struct State<V> {
+    pub heap: Vec<V>,
+    pub globals: SimpleStore,
+    pub frames: Vec<SimpleStore>,
+}
+
+impl<V: Clone> State<V> {
+    pub fn store(&self) -> &SimpleStore {
+        self.frames.last().expect("no active frame")
+    }
+
+    // Where `x` lives, as the current frame sees it
+    pub fn lookup(&self, x: &Symbol) -> Option<SimpleAddr> {
+        self.store().get(x).or_else(|| self.globals.get(x)).copied()
+    }
+
+    pub fn apply(&mut self, w: &Write<V>) {
+        match w {
+            Write::Alloc(l, v) => {
+                debug_assert_eq!(*l, self.heap.len());
+                self.heap.push(v.clone());
+            }
+            Write::Heap(l, v) => self.heap[*l] = v.clone(),
+            Write::Store(x, l) => {
+                self.frames.last_mut().expect("no active frame").insert(x.clone(), *l);
+            }
+            Write::Unbind(x) => {
+                self.frames.last_mut().expect("no active frame").remove(x);
+            }
+            Write::Global(x, l) => {
+                self.globals.insert(x.clone(), *l);
+            }
+            Write::PushFrame => self.frames.push(SimpleStore::new()),
+            Write::PopFrame => {
+                self.frames.pop();
+            }
+        }
+    }
+}
+
+// A single change to the state
+#[derive(Debug, Clone, PartialEq)]
+pub enum Write<V> {
+    // A new heap cell, always at the end of the heap
+    Alloc(SimpleAddr, V),
+    Heap(SimpleAddr, V),
+    // Bind a variable in the current frame
+    Store(Symbol, SimpleAddr),
+    // A variable of the current frame went out of scope
+    Unbind(Symbol),
+    Global(Symbol, SimpleAddr),
+    // A new, empty frame
+    PushFrame,
+    PopFrame,
+}
+
+// The statement at `loc`, and the writes made from when it started until
+// the next step did
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step<V> {
+    pub loc: Loc,
+    pub writes: Vec<Write<V>>,
+}
+
+// Rather than a snapshot per step, a trace keeps the initial state and the
+// writes made by each step, so that it grows with the number of writes
+// instead of the size of the heap times the number of steps.
+#[derive(Debug, Clone)]
+pub struct Trace<V> {
+    initial: State<V>,
+    steps: Vec<Step<V>>,
+}
+
+impl<V: Clone> Trace<V> {
+    pub fn new(initial: State<V>) -> Self {
+        Trace { initial, steps: Vec::new() }
+    }
+
+    pub fn step(&mut self, loc: Loc) {
+        self.steps.push(Step { loc, writes: Vec::new() });
+    }
+
+    // Writes made before the first step are part of the initial state
+    pub fn record(&mut self, w: Write<V>) {
+        match self.steps.last_mut() {
+            Some(step) => step.writes.push(w),
+            None => self.initial.apply(&w),
+        }
+    }
+
+    pub fn steps(&self) -> &[Step<V>] {
+        &self.steps
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    // The state when step `i` started; `state_at(len())` is the final state
+    pub fn state_at(&self, i: usize) -> State<V> {
+        let mut state = self.initial.clone();
+        for step in &self.steps[..i] {
+            for w in &step.writes {
+                state.apply(w);
+            }
+        }
+        state
+    }
+
+    // Call `f` on each step together with the state when it started, stopping
+    // at the first error. The state is replayed in place rather than copied,
+    // so a pass over the trace costs as much as its writes.
+    pub fn for_each_state<E>(&self, mut f: impl FnMut(&Step<V>, &State<V>) -> Result<(), E>) -> Result<(), E> {
+        let mut state = self.initial.clone();
+        for step in &self.steps {
+            f(step, &state)?;
+            for w in &step.writes {
+                state.apply(w);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Recording a trace: each statement starts a step
+impl<V: Clone> ExecutionObserver<V, SimpleAddr> for Trace<V> {
+    fn enter_stmt(&mut self, s: &Statement) {
+        self.step(s.loc);
+    }
+
+    fn call(&mut self, _f: &Symbol, _args: &[(Symbol, V)]) {
+        self.record(Write::PushFrame);
+    }
+
+    fn ret(&mut self, _f: &Symbol, _v: &V) {
+        self.record(Write::PopFrame);
+    }
+
+    fn alloc(&mut self, l: &SimpleAddr, v: &V) {
+        self.record(Write::Alloc(*l, v.clone()));
+    }
+
+    fn heap_write(&mut self, l: &SimpleAddr, v: &V) {
+        self.record(Write::Heap(*l, v.clone()));
+    }
+
+    fn store_write(&mut self, x: &Symbol, l: &SimpleAddr) {
+        self.record(Write::Store(x.clone(), *l));
+    }
+
+    fn store_remove(&mut self, x: &Symbol) {
+        self.record(Write::Unbind(x.clone()));
+    }
+
+    fn global_write(&mut self, x: &Symbol, l: &SimpleAddr) {
+        self.record(Write::Global(x.clone(), *l));
+    }
+}
+
+// Values that can appear in a JSON trace
+pub trait ToJson {
+    fn to_json(&self) -> String;
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl<V: Clone + ToJson> Trace<V> {
+    // Write one JSON object per line for each step, describing the state when
+    // the step started: its location (with line and column if `file` is
+    // given), the call depth, the bindings (address and value) of the globals
+    // and of the current frame, and the heap cells the step goes on to write,
+    // with their final values. Keys are sorted so that traces of similar runs
+    // diff cleanly.
+    pub fn write_json<W: io::Write>(&self, file: Option<&SourceFile>, out: &mut W) -> io::Result<()> {
+        let mut i = 0;
+        self.for_each_state(|step, state| {
+            let mut loc = format!("\"start\":{},\"end\":{}", step.loc.0, step.loc.1);
+            if let Some(file) = file {
+                let pos = file.position(step.loc.0);
+                loc.push_str(&format!(",\"line\":{},\"column\":{}", pos.line, pos.column));
+            }
+
+            let bindings = |store: &SimpleStore| {
+                let store: BTreeMap<_, _> = store.iter().map(|(x, l)| (&x.name, *l)).collect();
+                let store: Vec<String> = store.iter().map(|(x, l)| {
+                    format!("{}:{{\"addr\":{},\"value\":{}}}", json_string(x), l, state.heap[*l].to_json())
+                }).collect();
+                store.join(",")
+            };
+
+            let mut changed = BTreeMap::new();
+            for w in &step.writes {
+                if let Write::Alloc(l, v) | Write::Heap(l, v) = w {
+                    changed.insert(*l, v);
+                }
+            }
+            let changed: Vec<String> = changed.iter().map(|(l, v)| {
+                format!("{{\"addr\":{},\"value\":{}}}", l, v.to_json())
+            }).collect();
+
+            writeln!(out, "{{\"step\":{},\"loc\":{{{}}},\"depth\":{},\"globals\":{{{}}},\"store\":{{{}}},\"changed\":[{}]}}",
+                     i, loc, state.frames.len(), bindings(&state.globals), bindings(state.store()), changed.join(","))?;
+            i += 1;
+            Ok(())
+        })
+    }
+}
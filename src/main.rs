@@ -1,21 +1,129 @@
+use plaia::language::plaia::analysis::analyze;
 use plaia::language::plaia::concrete::*;
-use plaia::language::plaia::signed::*;
+use plaia::language::plaia::congruence::CongruenceValue;
+use plaia::language::plaia::constant::ConstValue;
+use plaia::language::plaia::debug::Debugger;
+use plaia::language::plaia::interval::IntervalValue;
+use plaia::language::plaia::parity::ParityValue;
+use plaia::language::plaia::signed::SignedValue;
 use plaia_language::language::plaia::parse;
+use plaia_language::language::plaia::source::SourceFile;
 
 use std::env;
+use std::io;
 use std::fs::read_to_string;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("usage: plaia [--fuel N] [--trace text|json] [--profile] file.pl [args...]");
+    eprintln!("       plaia debug [--fuel N] file.pl [args...]");
+    eprintln!("       plaia analyze [--domain sign|interval|const|parity|congruence] file.pl");
+    process::exit(2)
+}
 
 fn main() {
     let mut args: Vec<String> = env::args().collect();
     let _prog  = args.remove(0);
+    let command = match args.first().map(String::as_str) {
+        Some(c @ ("debug" | "analyze")) => c.to_string(),
+        _ => String::new(),
+    };
+    if !command.is_empty() {
+        args.remove(0);
+    }
+
+    // Flags come before the file; `--flag value` and `--flag=value` both work.
+    // Each command only takes the flags that it uses.
+    let allowed: &[&str] = match command.as_str() {
+        "debug" => &["--fuel"],
+        "analyze" => &["--domain"],
+        _ => &["--fuel", "--trace", "--profile"],
+    };
+    let mut config = RunConfig::default();
+    let mut domain = "sign".to_string();
+    while args.first().is_some_and(|a| a.starts_with("--")) {
+        let flag = args.remove(0);
+        let name = flag.split('=').next().unwrap_or_default();
+        if !allowed.contains(&name) {
+            let command = if command.is_empty() { "plaia".to_string() } else { format!("plaia {}", command) };
+            eprintln!("{} does not take {}", command, name);
+            usage()
+        }
+        if flag == "--profile" {
+            config.profile = true;
+            continue;
+        }
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None if !args.is_empty() => (flag, args.remove(0)),
+            None => usage(),
+        };
+        match name.as_str() {
+            "--domain" => domain = value,
+            "--fuel" => config.fuel = Some(value.parse::<u64>().unwrap_or_else(|_| usage())),
+            "--trace" => config.trace = match value.as_str() {
+                "text" => TraceFormat::Text,
+                "json" => TraceFormat::Json,
+                _ => usage(),
+            },
+            _ => usage(),
+        }
+    }
+    if args.is_empty() {
+        usage()
+    }
     let file = args.remove(0);
 
-    let contents = read_to_string(file)
+    let contents = read_to_string(&file)
         .expect("Something went wrong reading the file");
+    let source = SourceFile::new(file, contents);
+
+    let prog = match parse::parse_source(&source) {
+        Ok(prog) => prog,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    if command == "analyze" {
+        let report = match domain.as_str() {
+            "sign" => analyze::<SignedValue>(&prog).map(|a| a.report(&source)),
+            "interval" => analyze::<IntervalValue>(&prog).map(|a| a.report(&source)),
+            "const" => analyze::<ConstValue>(&prog).map(|a| a.report(&source)),
+            "parity" => analyze::<ParityValue>(&prog).map(|a| a.report(&source)),
+            "congruence" => analyze::<CongruenceValue>(&prog).map(|a| a.report(&source)),
+            _ => usage(),
+        };
+        match report {
+            Ok(report) => print!("{}", report),
+            Err(err) => {
+                eprintln!("{}", err.render(&source));
+                process::exit(1)
+            }
+        }
+        return;
+    }
 
-    let prog = parse::parse_module(&contents);
+    if command == "debug" {
+        let (trace, err) = match record_run(&prog, args, config.fuel, Vec::new()) {
+            Ok(run) => run,
+            Err(err) => {
+                eprintln!("{}", err.render(&source));
+                process::exit(1)
+            }
+        };
+        let stdin = io::stdin();
+        Debugger::new(&trace, &source, err.as_ref())
+            .run(stdin.lock(), &mut io::stdout())
+            .expect("cannot talk to the terminal");
+        return;
+    }
 
-    concrete_run(prog, args, Some(&contents));
+    if let Err(err) = concrete_run(prog, args, &config, Some(&source)) {
+        eprintln!("{}", err.render(&source));
+        process::exit(1)
+    }
     // // let e = parse::parse_expression("3");
     // // println!("Eval {:?}", concrete_eval::<SimpleValue>(e));
 